@@ -0,0 +1,215 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use crate::cli::get_cli_args;
+
+pub const DEFAULT_RESOLUTION: Resolution = Resolution {
+    width: 800,
+    height: 600,
+};
+
+/// Smaller resolutions are scaled up by a whole factor until the window is at
+/// least this big.
+const MIN_WINDOW_WIDTH: u32 = 640;
+const MIN_WINDOW_HEIGHT: u32 = 480;
+
+/// Size of the cartridge's framebuffer, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    /// Whole-number factor the framebuffer is scaled by in the window.
+    pub fn scale(self) -> u32 {
+        MIN_WINDOW_WIDTH
+            .div_ceil(self.width)
+            .max(MIN_WINDOW_HEIGHT.div_ceil(self.height))
+            .max(1)
+    }
+
+    pub fn window_size(self) -> (u32, u32) {
+        (self.width * self.scale(), self.height * self.scale())
+    }
+
+    pub const fn pixels(self) -> usize {
+        self.width as usize * self.height as usize
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| anyhow::anyhow!("resolution must look like 320x240, got `{s}`"))?;
+        let width: u32 = width.trim().parse()?;
+        let height: u32 = height.trim().parse()?;
+        if width == 0 || height == 0 || width > 4096 || height > 4096 {
+            anyhow::bail!("resolution {width}x{height} is out of range");
+        }
+        Ok(Self { width, height })
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Settings read from the `<cartridge>.toml` file next to the cartridge.
+///
+/// Only flat `key = value` lines are understood, which is all the manifest
+/// needs for now.
+#[derive(Default)]
+pub struct Manifest {
+    pub resolution: Option<Resolution>,
+}
+
+impl Manifest {
+    pub fn path_for(cartridge: &Path) -> PathBuf {
+        cartridge.with_extension("toml")
+    }
+
+    /// Loads the manifest for `cartridge`. A missing file is not an error.
+    pub fn load(cartridge: &Path) -> anyhow::Result<Self> {
+        let path = Self::path_for(cartridge);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = fs::read_to_string(&path)?;
+        Self::parse(&text).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut manifest = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("line {}: expected `key = value`", i + 1))?;
+            let value = value.trim().trim_matches('"');
+
+            match key.trim() {
+                "resolution" => manifest.resolution = Some(value.parse()?),
+                other => log::warn!("ignoring unknown manifest key `{other}`"),
+            }
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// Cuts a `#` comment off `line`. A `#` only starts a comment at the start
+/// of the line or after whitespace, so a value like `a#b` keeps it.
+fn strip_comment(line: &str) -> &str {
+    let mut previous = None;
+    for (i, ch) in line.char_indices() {
+        if ch == '#' && previous.is_none_or(char::is_whitespace) {
+            return &line[..i];
+        }
+        previous = Some(ch);
+    }
+    line
+}
+
+/// The framebuffer resolution for this run: `--resolution` if given, then the
+/// cartridge manifest, then [`DEFAULT_RESOLUTION`].
+pub fn get_resolution() -> Resolution {
+    static RESOLUTION: OnceLock<Resolution> = OnceLock::new();
+    *RESOLUTION.get_or_init(|| {
+        let args = get_cli_args();
+        if let Some(resolution) = args.resolution {
+            return resolution;
+        }
+
+        match Manifest::load(&args.cartridge) {
+            Ok(manifest) => manifest.resolution.unwrap_or(DEFAULT_RESOLUTION),
+            Err(e) => {
+                log::error!("failed to read cartridge manifest: {e}");
+                DEFAULT_RESOLUTION
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_resolutions() {
+        assert_eq!(
+            "320x240".parse::<Resolution>().unwrap(),
+            Resolution {
+                width: 320,
+                height: 240
+            }
+        );
+        assert_eq!(
+            " 64 x 48 ".parse::<Resolution>().unwrap().to_string(),
+            "64x48"
+        );
+        for bad in ["320", "0x240", "320x0", "4097x10", "axb", "-1x10", ""] {
+            assert!(bad.parse::<Resolution>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn scale_fills_the_minimum_window() {
+        let scale = |width, height| Resolution { width, height }.scale();
+        assert_eq!(scale(160, 144), 4);
+        assert_eq!(scale(320, 240), 2);
+        assert_eq!(scale(800, 600), 1);
+        assert_eq!(scale(4096, 4096), 1);
+        // the taller side decides
+        assert_eq!(scale(640, 100), 5);
+        assert_eq!(
+            Resolution {
+                width: 160,
+                height: 144
+            }
+            .window_size(),
+            (640, 576)
+        );
+    }
+
+    #[test]
+    fn manifest_reads_resolution_and_skips_comments() {
+        let manifest =
+            Manifest::parse("# settings\n\nresolution = \"160x144\" # gameboy\nname = a#b\n")
+                .unwrap();
+        assert_eq!(manifest.resolution.unwrap().to_string(), "160x144");
+        assert!(Manifest::parse("").unwrap().resolution.is_none());
+    }
+
+    #[test]
+    fn manifest_reports_bad_lines() {
+        let error = Manifest::parse("resolution 160x144\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 1: expected `key = value`");
+        assert!(Manifest::parse("resolution = 0x0\n").is_err());
+    }
+
+    #[test]
+    fn comments_need_whitespace_before_them() {
+        assert_eq!(strip_comment("# all of it"), "");
+        assert_eq!(strip_comment("a = 1 # note"), "a = 1 ");
+        assert_eq!(strip_comment("a = 1\t# note"), "a = 1\t");
+        assert_eq!(strip_comment("color = #ff0000"), "color = ");
+        assert_eq!(strip_comment("color=#ff0000"), "color=#ff0000");
+        assert_eq!(strip_comment("a = b#c"), "a = b#c");
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use log::{Level, LevelFilter};
+
+/// Messages a cartridge may log per second before the rest are dropped.
+pub const MAX_MESSAGES_PER_SECOND: u32 = 200;
+/// Size at which the log file is rotated.
+const MAX_LOG_FILE_SIZE: u64 = 8 << 20;
+/// Rotated files kept next to the current one, as `<path>.1` and up.
+const KEPT_LOG_FILES: usize = 3;
+/// Messages kept around for the dev console.
+const RECENT_MESSAGES: usize = 200;
+/// Messages kept from the current call into the cartridge, for
+/// [`crate::panic_capture`].
+const MAX_CALL_MESSAGES: usize = 16;
+
+/// Where cartridge log messages go: the `log` crate under a
+/// `cartridge::<name>` target, and optionally a log file of their own.
+pub struct CartridgeLog {
+    target: String,
+    max_level: LevelFilter,
+    limiter: RateLimiter,
+    file: Option<RotatingFile>,
+    started: Instant,
+    recent: VecDeque<String>,
+    written: u64,
+    call_messages: Vec<String>,
+}
+
+impl CartridgeLog {
+    pub fn new(cartridge: &Path, max_level: LevelFilter, file: Option<&Path>) -> io::Result<Self> {
+        let name = cartridge
+            .file_stem()
+            .map_or_else(|| "cartridge".into(), |stem| stem.to_string_lossy());
+        let file = file.map(RotatingFile::open).transpose()?;
+
+        Ok(Self {
+            target: format!("cartridge::{name}"),
+            max_level,
+            limiter: RateLimiter::default(),
+            file,
+            started: Instant::now(),
+            recent: VecDeque::new(),
+            written: 0,
+            call_messages: Vec::new(),
+        })
+    }
+
+    pub fn log(&mut self, level: Level, message: &str) {
+        // kept even when filtered out, a panic message is needed regardless
+        if self.call_messages.len() == MAX_CALL_MESSAGES {
+            self.call_messages.remove(0);
+        }
+        self.call_messages.push(message.to_string());
+
+        if level > self.max_level {
+            return;
+        }
+
+        match self.limiter.check(Instant::now()) {
+            RateLimit::Allow { suppressed } => {
+                if suppressed > 0 {
+                    self.write(
+                        Level::Warn,
+                        &format!("suppressed {suppressed} messages over the rate limit"),
+                    );
+                }
+                self.write(level, message);
+            }
+            RateLimit::Drop => {}
+        }
+    }
+
+    /// Starts collecting [`Self::call_messages`] afresh.
+    pub fn begin_call(&mut self) {
+        self.call_messages.clear();
+    }
+
+    /// The last messages logged since [`Self::begin_call`], oldest first.
+    pub fn call_messages(&self) -> &[String] {
+        &self.call_messages
+    }
+
+    /// The last messages that got through, oldest first.
+    pub const fn recent(&self) -> &VecDeque<String> {
+        &self.recent
+    }
+
+    /// How many messages got through so far, to tell which of
+    /// [`Self::recent`] are new.
+    pub const fn written(&self) -> u64 {
+        self.written
+    }
+
+    fn write(&mut self, level: Level, message: &str) {
+        log::log!(target: &self.target, level, "{message}");
+
+        if self.recent.len() == RECENT_MESSAGES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(format!("{level:<5} {message}"));
+        self.written += 1;
+
+        if let Some(file) = &mut self.file {
+            let line = format!(
+                "{:>10.3} {level:<5} {message}\n",
+                self.started.elapsed().as_secs_f64()
+            );
+            if let Err(e) = file.write_line(&line) {
+                log::error!("failed to write cartridge log, disabling it: {e}");
+                self.file = None;
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum RateLimit {
+    /// Log the message, after reporting how many were dropped before it.
+    Allow {
+        suppressed: u32,
+    },
+    Drop,
+}
+
+/// Allows [`MAX_MESSAGES_PER_SECOND`] messages per one second window.
+#[derive(Default)]
+struct RateLimiter {
+    window_start: Option<Instant>,
+    count: u32,
+    suppressed: u32,
+}
+
+impl RateLimiter {
+    fn check(&mut self, now: Instant) -> RateLimit {
+        let expired = self
+            .window_start
+            .is_none_or(|start| now.duration_since(start) >= Duration::from_secs(1));
+        if expired {
+            self.window_start = Some(now);
+            self.count = 0;
+        }
+
+        if self.count < MAX_MESSAGES_PER_SECOND {
+            self.count += 1;
+            RateLimit::Allow {
+                suppressed: std::mem::take(&mut self.suppressed),
+            }
+        } else {
+            self.suppressed += 1;
+            RateLimit::Drop
+        }
+    }
+}
+
+/// A log file that starts fresh every session and moves on to a new file
+/// once it grows past [`MAX_LOG_FILE_SIZE`], keeping the previous ones as
+/// `<path>.1`, `<path>.2`, ...
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        rotate(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: File::create(path)?,
+            written: 0,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.written + line.len() as u64 > MAX_LOG_FILE_SIZE {
+            rotate(&self.path)?;
+            self.file = File::create(&self.path)?;
+            self.written = 0;
+        }
+        // unbuffered, so the last messages survive a crash
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Shifts `path` to `<path>.1`, `<path>.1` to `<path>.2` and so on, dropping
+/// the oldest.
+fn rotate(path: &Path) -> io::Result<()> {
+    for index in (1..KEPT_LOG_FILES).rev() {
+        rename_if_exists(&rotated_path(path, index), &rotated_path(path, index + 1))?;
+    }
+    rename_if_exists(path, &rotated_path(path, 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit_reports_dropped_messages() {
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+
+        for _ in 0..MAX_MESSAGES_PER_SECOND {
+            assert_eq!(limiter.check(start), RateLimit::Allow { suppressed: 0 });
+        }
+        assert_eq!(limiter.check(start), RateLimit::Drop);
+        assert_eq!(
+            limiter.check(start + Duration::from_millis(999)),
+            RateLimit::Drop
+        );
+
+        let next = start + Duration::from_secs(1);
+        assert_eq!(limiter.check(next), RateLimit::Allow { suppressed: 2 });
+        assert_eq!(limiter.check(next), RateLimit::Allow { suppressed: 0 });
+    }
+
+    #[test]
+    fn rotate_keeps_newest_files() {
+        let dir = std::env::temp_dir().join(format!("cartridge-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.log");
+
+        for session in 0..=KEPT_LOG_FILES {
+            let mut file = RotatingFile::open(&path).unwrap();
+            file.write_line(&format!("session {session}\n")).unwrap();
+        }
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), format!("session {KEPT_LOG_FILES}\n"));
+        for index in 1..=KEPT_LOG_FILES {
+            let session = KEPT_LOG_FILES - index;
+            assert_eq!(
+                read(&rotated_path(&path, index)),
+                format!("session {session}\n")
+            );
+        }
+        assert!(!rotated_path(&path, KEPT_LOG_FILES + 1).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use std::{fmt::Write as _, fs, path::Path, time::Instant};
+
+use crate::profiler::{FrameProfile, get_profile_frames};
+
+const PID: u32 = 1;
+const TID: u32 = 1;
+
+/// Serializes profiled frames as Chrome Trace Event JSON, which can be loaded
+/// in Perfetto or `about:tracing`. Timestamps are relative to the first frame.
+pub fn chrome_trace_json(frames: &[FrameProfile]) -> String {
+    let Some(epoch) = frames.first().map(|frame| frame.start) else {
+        return "{\"traceEvents\":[]}".to_string();
+    };
+    let micros = |instant: Instant| instant.duration_since(epoch).as_secs_f64() * 1_000_000.0;
+
+    let mut events = Vec::new();
+    for frame in frames {
+        let ts = micros(frame.start);
+        events.push(format!(
+            "{{\"name\":\"frame {}\",\"cat\":\"frame\",\"ph\":\"i\",\"s\":\"g\",\"ts\":{ts:.3},\"pid\":{PID},\"tid\":{TID}}}",
+            frame.index
+        ));
+        events.push(format!(
+            "{{\"name\":\"frame\",\"cat\":\"frame\",\"ph\":\"X\",\"ts\":{ts:.3},\"dur\":{:.3},\"pid\":{PID},\"tid\":{TID},\"args\":{{\"index\":{}}}}}",
+            frame.duration.as_secs_f64() * 1_000_000.0,
+            frame.index
+        ));
+
+        for scope in &frame.scopes {
+            events.push(format!(
+                "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":{PID},\"tid\":{TID}}}",
+                escape_json(&scope.label),
+                scope.category,
+                micros(scope.start),
+                scope.duration.as_secs_f64() * 1_000_000.0,
+            ));
+        }
+
+        // JSON has no NaN or infinity, and a sum can still overflow
+        let mut counters: Vec<_> = frame
+            .counters
+            .iter()
+            .filter(|(_, value)| value.is_finite())
+            .collect();
+        counters.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        for (name, value) in counters {
+            events.push(format!(
+                "{{\"name\":\"{}\",\"cat\":\"counter\",\"ph\":\"C\",\"ts\":{ts:.3},\"pid\":{PID},\"tid\":{TID},\"args\":{{\"value\":{value}}}}}",
+                escape_json(name),
+            ));
+        }
+    }
+
+    let mut json = String::from("{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n");
+    json.push_str(&events.join(",\n"));
+    json.push_str("\n]}\n");
+    json
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes the profiler's current window of frames to `path`.
+pub fn save_chrome_trace(path: &Path) -> anyhow::Result<()> {
+    let frames = get_profile_frames();
+    fs::write(path, chrome_trace_json(&frames))?;
+    log::info!(
+        "wrote {} profiled frames to {}",
+        frames.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::profiler::{CATEGORY_GUEST, ScopeSample};
+
+    const LABEL: &str = "say \"hi\" \\ to\n\t\r\u{1}\u{7f} ünï";
+
+    fn events(frames: &[FrameProfile]) -> Vec<Value> {
+        let trace: Value = serde_json::from_str(&chrome_trace_json(frames)).unwrap();
+        trace["traceEvents"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn labels_survive_escaping() {
+        let escaped: String = serde_json::from_str(&format!("\"{}\"", escape_json(LABEL))).unwrap();
+        assert_eq!(escaped, LABEL);
+    }
+
+    #[test]
+    fn frames_become_trace_events() {
+        assert!(events(&[]).is_empty());
+
+        let epoch = Instant::now();
+        let ms = Duration::from_millis;
+        let frames = [
+            FrameProfile {
+                index: 7,
+                start: epoch,
+                duration: ms(16),
+                scopes: vec![ScopeSample {
+                    label: LABEL.to_string(),
+                    parent: None,
+                    category: CATEGORY_GUEST,
+                    depth: 0,
+                    start: epoch + ms(2),
+                    duration: ms(3),
+                }],
+                counters: HashMap::from([
+                    ("sprites".to_string(), 12.5),
+                    ("overflowed".to_string(), f64::INFINITY),
+                ]),
+            },
+            FrameProfile {
+                index: 8,
+                start: epoch + ms(16),
+                duration: ms(17),
+                scopes: Vec::new(),
+                counters: HashMap::new(),
+            },
+        ];
+
+        let common = |mut event: Value| {
+            event["pid"] = json!(PID);
+            event["tid"] = json!(TID);
+            event
+        };
+        assert_eq!(
+            events(&frames),
+            [
+                common(json!({"name": "frame 7", "cat": "frame", "ph": "i", "s": "g", "ts": 0.0})),
+                common(json!({
+                    "name": "frame", "cat": "frame", "ph": "X", "ts": 0.0, "dur": 16000.0,
+                    "args": {"index": 7},
+                })),
+                common(json!({
+                    "name": LABEL, "cat": "guest", "ph": "X", "ts": 2000.0, "dur": 3000.0,
+                })),
+                common(json!({
+                    "name": "sprites", "cat": "counter", "ph": "C", "ts": 0.0,
+                    "args": {"value": 12.5},
+                })),
+                common(
+                    json!({"name": "frame 8", "cat": "frame", "ph": "i", "s": "g", "ts": 16000.0})
+                ),
+                common(json!({
+                    "name": "frame", "cat": "frame", "ph": "X", "ts": 16000.0, "dur": 17000.0,
+                    "args": {"index": 8},
+                })),
+            ]
+        );
+    }
+}
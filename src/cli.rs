@@ -0,0 +1,180 @@
+use std::{path::PathBuf, sync::OnceLock};
+
+use log::LevelFilter;
+
+use crate::{cartridge::Resolution, filters::FilterPipeline, mouse::MouseSettings};
+
+const DEFAULT_CARTRIDGE: &str = "tests/goosegpu.wasm";
+
+const USAGE: &str = "\
+usage: gooseboy-emulator [options] [cartridge.wasm]
+
+options:
+    --resolution <WxH>    framebuffer size, e.g. 160x144 (overrides the
+                          cartridge manifest, default 800x600)
+    --filter <list>       display filters, comma separated: smooth, nearest,
+                          scanlines[=0..1], palette=gameboy|grayscale|#rrggbb:...,
+                          colorblind=protanopia|deuteranopia|tritanopia
+    --terminal            draw to this terminal instead of opening a window,
+                          e.g. over SSH (logs go to gooseboy-emulator.log)
+    --keyboard-gamepad    connect gamepad 0 and drive it from the keyboard
+    --mouse-sensitivity <factor>
+                          scale relative mouse motion (default 1)
+    --invert-mouse-y      flip relative mouse motion vertically
+    --log-level <level>   most verbose cartridge log level to show: trace, debug,
+                          info (default), warn, error or off
+    --log-file <path>     also write cartridge logs to a file, rotating the
+                          previous ones to <path>.1, <path>.2, ...
+    --record-input <path>
+                          record every input event and clock reading to a
+                          movie file
+    --play-input <path>   play a recorded movie instead of reading input, and
+                          quit when it ends
+    --input-script <path> drive the cartridge from a timeline of key and mouse
+                          events instead of reading input
+    --console-script <path>
+                          run dev console commands from a file once the
+                          cartridge has started
+    --trace <path>        write a Chrome trace of the last profiled frames on exit
+    --trace-host-calls    record every host function call as a profiler scope
+    --host-call-stats     count host function calls per frame (F6 shows them)
+    --host-call-log <path>
+                          stream every host function call with its arguments
+                          to a file
+    --guest-profile <path>
+                          sample the cartridge's wasm stack and write a Firefox
+                          Profiler file on exit
+    -h, --help            print this message";
+
+#[allow(clippy::struct_excessive_bools)]
+pub struct CliArgs {
+    pub cartridge: PathBuf,
+    pub resolution: Option<Resolution>,
+    pub filters: FilterPipeline,
+    pub terminal: bool,
+    pub keyboard_gamepad: bool,
+    pub mouse: MouseSettings,
+    pub log_level: LevelFilter,
+    pub log_file: Option<PathBuf>,
+    pub record_input: Option<PathBuf>,
+    pub play_input: Option<PathBuf>,
+    pub input_script: Option<PathBuf>,
+    pub console_script: Option<PathBuf>,
+    pub trace_path: Option<PathBuf>,
+    pub trace_host_calls: bool,
+    pub host_call_stats: bool,
+    pub host_call_log: Option<PathBuf>,
+    pub guest_profile: Option<PathBuf>,
+}
+
+impl CliArgs {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut cartridge = None;
+        let mut resolution = None;
+        let mut filters = FilterPipeline::default();
+        let mut terminal = false;
+        let mut keyboard_gamepad = false;
+        let mut mouse = MouseSettings::default();
+        let mut log_level = LevelFilter::Info;
+        let mut log_file = None;
+        let mut record_input = None;
+        let mut play_input = None;
+        let mut input_script = None;
+        let mut console_script = None;
+        let mut trace_path = None;
+        let mut trace_host_calls = false;
+        let mut host_call_stats = false;
+        let mut host_call_log = None;
+        let mut guest_profile = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--resolution" => resolution = Some(next_value(&mut args, &arg)?.parse()?),
+                "--filter" => filters = next_value(&mut args, &arg)?.parse()?,
+                "--terminal" => terminal = true,
+                "--keyboard-gamepad" => keyboard_gamepad = true,
+                "--mouse-sensitivity" => {
+                    mouse.sensitivity = parse_sensitivity(&next_value(&mut args, &arg)?)?;
+                }
+                "--invert-mouse-y" => mouse.invert_y = true,
+                "--log-level" => log_level = next_value(&mut args, &arg)?.parse()?,
+                "--log-file" => log_file = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--record-input" => {
+                    record_input = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--play-input" => play_input = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--input-script" => {
+                    input_script = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--console-script" => {
+                    console_script = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--trace" => trace_path = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--trace-host-calls" => trace_host_calls = true,
+                "--host-call-stats" => host_call_stats = true,
+                "--host-call-log" => {
+                    host_call_log = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--guest-profile" => {
+                    guest_profile = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                flag if flag.starts_with('-') => anyhow::bail!("unknown option: {flag}"),
+                _ if cartridge.is_some() => anyhow::bail!("unexpected argument: {arg}"),
+                _ => cartridge = Some(PathBuf::from(arg)),
+            }
+        }
+
+        if play_input.is_some() && input_script.is_some() {
+            anyhow::bail!("--play-input and --input-script can't be used together");
+        }
+
+        Ok(Self {
+            cartridge: cartridge.unwrap_or_else(|| PathBuf::from(DEFAULT_CARTRIDGE)),
+            resolution,
+            filters,
+            terminal,
+            keyboard_gamepad,
+            mouse,
+            log_level,
+            log_file,
+            record_input,
+            play_input,
+            input_script,
+            console_script,
+            trace_path,
+            trace_host_calls,
+            host_call_stats,
+            host_call_log,
+            guest_profile,
+        })
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> anyhow::Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow::anyhow!("{flag} expects a value"))
+}
+
+fn parse_sensitivity(value: &str) -> anyhow::Result<f64> {
+    let sensitivity: f64 = value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid mouse sensitivity: {value}"))?;
+    if !sensitivity.is_finite() || sensitivity <= 0.0 {
+        anyhow::bail!("mouse sensitivity must be above 0, got {value}");
+    }
+    Ok(sensitivity)
+}
+
+pub fn get_cli_args() -> &'static CliArgs {
+    static CLI_ARGS: OnceLock<CliArgs> = OnceLock::new();
+    CLI_ARGS.get_or_init(|| {
+        CliArgs::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::exit(2);
+        })
+    })
+}
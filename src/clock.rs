@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+
+use crate::utils::get_time_nanos;
+
+/// Time as the cartridge sees it, through `update` and
+/// `system::get_time_nanos`. The dev console's `speed` command scales it.
+///
+/// While a movie is recorded every reading is kept, and while one plays
+/// the recorded readings are handed out instead of the real time.
+pub struct GuestClock {
+    speed: f64,
+    /// Real and guest time when the speed last changed.
+    real_base: i64,
+    guest_base: i64,
+    /// Readings since the last [`Self::take_recorded`], while recording.
+    recorded: Option<Vec<i64>>,
+    /// Readings still to hand out this frame, while replaying.
+    replayed: Option<VecDeque<i64>>,
+    /// The last reading, repeated if a replayed frame runs out of them.
+    last: i64,
+}
+
+impl GuestClock {
+    pub const fn new() -> Self {
+        Self {
+            speed: 1.0,
+            real_base: 0,
+            guest_base: 0,
+            recorded: None,
+            replayed: None,
+            last: 0,
+        }
+    }
+
+    pub fn now(&mut self) -> i64 {
+        let now = match &mut self.replayed {
+            Some(replayed) => replayed.pop_front().unwrap_or(self.last),
+            None => self.at(get_time_nanos()),
+        };
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push(now);
+        }
+        self.last = now;
+        now
+    }
+
+    /// Starts keeping every reading for [`Self::take_recorded`].
+    pub fn record(&mut self) {
+        self.recorded = Some(Vec::new());
+    }
+
+    pub fn take_recorded(&mut self) -> Vec<i64> {
+        self.recorded
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Hands out `readings` in order from now on, instead of the real time.
+    pub fn replay(&mut self, readings: Vec<i64>) {
+        self.replayed = Some(readings.into());
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    fn at(&self, real: i64) -> i64 {
+        self.guest_base + ((real - self.real_base) as f64 * self.speed) as i64
+    }
+
+    pub const fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Changes the speed from now on, without jumping.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.rebase(get_time_nanos(), speed);
+    }
+
+    fn rebase(&mut self, real: i64, speed: f64) {
+        self.guest_base = self.at(real);
+        self.real_base = real;
+        self.speed = speed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed_changes_do_not_jump() {
+        let mut clock = GuestClock::new();
+        assert_eq!(clock.at(1_000), 1_000);
+
+        clock.rebase(1_000, 2.0);
+        assert_eq!(clock.at(1_000), 1_000);
+        assert_eq!(clock.at(1_500), 2_000);
+
+        clock.rebase(1_500, 0.0);
+        assert_eq!(clock.at(9_000), 2_000);
+    }
+
+    #[test]
+    fn replays_recorded_readings() {
+        let mut clock = GuestClock::new();
+        clock.record();
+        clock.replay(vec![5, 9]);
+        assert_eq!([clock.now(), clock.now(), clock.now()], [5, 9, 9]);
+        assert_eq!(clock.take_recorded(), [5, 9, 9]);
+        assert!(clock.take_recorded().is_empty());
+
+        clock.replay(vec![12]);
+        assert_eq!(clock.now(), 12);
+    }
+}
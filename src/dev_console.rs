@@ -0,0 +1,379 @@
+//! Commands for the dev console overlay and `--console-script`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use wasmtime::{Val, ValType};
+
+use crate::{
+    cli::get_cli_args,
+    gpu::{mesh_registry::get_mesh_registry, texture_registry::get_texture_registry},
+    host_calls::format_vals,
+    save_state::{load_state, save_state},
+    utils::timestamped_path,
+    wasm::{WASMRuntime, guest_call},
+};
+
+pub const HELP: &str = "\
+peek <addr> [len]          show guest memory as hex (default 64 bytes)
+poke <addr> <byte>...      write bytes to guest memory
+dump <addr> <len> <path>   write guest memory to a file
+call <export> [args]...    call an exported function
+textures                   list GPU textures
+meshes                     list GPU meshes
+speed [factor]             show or set the clock speed, 0 pauses time
+screenshot [path]          save the framebuffer as a PNG
+save [path]                save the cartridge state
+load [path]                load a saved state
+clear                      clear the console";
+
+/// Most bytes `peek` shows at once.
+const MAX_PEEK: usize = 1024;
+
+/// Runs one command line and returns its output. `clear` is handled by the
+/// overlay.
+pub fn run_command(wasm: &mut WASMRuntime, line: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else {
+        return Ok(Vec::new());
+    };
+    let args: Vec<&str> = words.collect();
+
+    match command {
+        "help" => Ok(HELP.lines().map(String::from).collect()),
+        "peek" => peek(wasm, &args),
+        "poke" => poke(wasm, &args),
+        "dump" => dump(wasm, &args),
+        "call" => call(wasm, &args),
+        "textures" => textures(wasm),
+        "meshes" => meshes(wasm),
+        "speed" => speed(wasm, &args),
+        "screenshot" => screenshot(wasm, &args),
+        "save" => {
+            let path = state_path(&args);
+            save_state(wasm, &path)?;
+            Ok(vec![format!("saved state to {}", path.display())])
+        }
+        "load" => {
+            let path = state_path(&args);
+            load_state(wasm, &path)?;
+            Ok(vec![format!("loaded state from {}", path.display())])
+        }
+        _ => anyhow::bail!("unknown command `{command}`, try `help`"),
+    }
+}
+
+/// Runs every line of a command file, skipping blank lines and `#`
+/// comments. Output and errors are logged and returned; a failing command
+/// does not stop the rest.
+pub fn run_script(wasm: &mut WASMRuntime, path: &Path) -> Vec<String> {
+    let script = match fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => {
+            let line = format!("failed to read {}: {e}", path.display());
+            log::error!("{line}");
+            return vec![line];
+        }
+    };
+
+    let mut output = Vec::new();
+    for (number, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        log::info!("> {line}");
+        output.push(format!("> {line}"));
+        match run_command(wasm, line) {
+            Ok(lines) => {
+                for line in lines {
+                    log::info!("{line}");
+                    output.push(line);
+                }
+            }
+            Err(e) => {
+                let line = format!("{}:{}: {e:#}", path.display(), number + 1);
+                log::error!("{line}");
+                output.push(line);
+            }
+        }
+    }
+    output
+}
+
+fn parse_number(text: &str) -> anyhow::Result<u64> {
+    let parsed = text
+        .strip_prefix("0x")
+        .map_or_else(|| text.parse(), |hex| u64::from_str_radix(hex, 16));
+    parsed.with_context(|| format!("`{text}` is not a number"))
+}
+
+fn parse_usize(text: &str) -> anyhow::Result<usize> {
+    Ok(usize::try_from(parse_number(text)?)?)
+}
+
+fn arg<'a>(args: &[&'a str], index: usize, name: &str) -> anyhow::Result<&'a str> {
+    args.get(index)
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("missing {name}"))
+}
+
+fn guest_memory(wasm: &mut WASMRuntime) -> &mut [u8] {
+    let memory = wasm.memory.get_mut().unwrap();
+    memory.data_mut(wasm.store.get_mut())
+}
+
+fn memory_range(mem: &mut [u8], addr: usize, len: usize) -> anyhow::Result<&mut [u8]> {
+    let len_in_bounds = addr.checked_add(len).is_some_and(|end| end <= mem.len());
+    if !len_in_bounds {
+        anyhow::bail!(
+            "{len} bytes at {addr:#x} are outside guest memory ({:#x} bytes)",
+            mem.len()
+        );
+    }
+    Ok(&mut mem[addr..addr + len])
+}
+
+fn peek(wasm: &mut WASMRuntime, args: &[&str]) -> anyhow::Result<Vec<String>> {
+    let addr = parse_usize(arg(args, 0, "address")?)?;
+    let len = args.get(1).map_or(Ok(64), |len| parse_usize(len))?;
+    if len > MAX_PEEK {
+        anyhow::bail!("peek shows at most {MAX_PEEK} bytes, use dump for more");
+    }
+
+    let bytes = memory_range(guest_memory(wasm), addr, len)?;
+    Ok(bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        char::from(b)
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}  {:<47}  {ascii}", addr + row * 16, hex.join(" "))
+        })
+        .collect())
+}
+
+fn poke(wasm: &mut WASMRuntime, args: &[&str]) -> anyhow::Result<Vec<String>> {
+    let addr = parse_usize(arg(args, 0, "address")?)?;
+    let bytes = args[1..]
+        .iter()
+        .map(|text| Ok(u8::try_from(parse_number(text)?)?))
+        .collect::<anyhow::Result<Vec<u8>>>()?;
+    if bytes.is_empty() {
+        anyhow::bail!("missing bytes to write");
+    }
+
+    memory_range(guest_memory(wasm), addr, bytes.len())?.copy_from_slice(&bytes);
+    Ok(vec![format!("wrote {} bytes at {addr:#x}", bytes.len())])
+}
+
+fn dump(wasm: &mut WASMRuntime, args: &[&str]) -> anyhow::Result<Vec<String>> {
+    let addr = parse_usize(arg(args, 0, "address")?)?;
+    let len = parse_usize(arg(args, 1, "length")?)?;
+    let path = arg(args, 2, "path")?;
+
+    let bytes = memory_range(guest_memory(wasm), addr, len)?;
+    fs::write(path, bytes).with_context(|| format!("failed to write {path}"))?;
+    Ok(vec![format!("wrote {len} bytes at {addr:#x} to {path}")])
+}
+
+fn parse_val(ty: &ValType, text: &str) -> anyhow::Result<Val> {
+    let parse = || -> anyhow::Result<Val> {
+        Ok(match ty {
+            ValType::I32 => Val::I32(if text.starts_with("0x") {
+                u32::try_from(parse_number(text)?)?.cast_signed()
+            } else {
+                text.parse()?
+            }),
+            ValType::I64 => Val::I64(if text.starts_with("0x") {
+                parse_number(text)?.cast_signed()
+            } else {
+                text.parse()?
+            }),
+            ValType::F32 => Val::F32(text.parse::<f32>()?.to_bits()),
+            ValType::F64 => Val::F64(text.parse::<f64>()?.to_bits()),
+            _ => anyhow::bail!("parameters of type {ty} are not supported"),
+        })
+    };
+    parse().with_context(|| format!("`{text}` is not a valid {ty}"))
+}
+
+fn call(wasm: &mut WASMRuntime, args: &[&str]) -> anyhow::Result<Vec<String>> {
+    let name = arg(args, 0, "export name")?;
+    let store = wasm.store.get_mut();
+    let func = wasm
+        .instance
+        .get_mut()
+        .unwrap()
+        .get_func(&mut *store, name)
+        .ok_or_else(|| anyhow::anyhow!("cartridge exports no function `{name}`"))?;
+    let ty = func.ty(&*store);
+
+    let args = &args[1..];
+    if ty.params().len() != args.len() {
+        anyhow::bail!("`{name}` takes {} arguments: {ty}", ty.params().len());
+    }
+    let params = ty
+        .params()
+        .zip(args)
+        .map(|(ty, text)| parse_val(&ty, text))
+        .collect::<anyhow::Result<Vec<Val>>>()?;
+    let mut results = ty
+        .results()
+        .map(|ty| Val::default_for_ty(&ty))
+        .collect::<Option<Vec<Val>>>()
+        .ok_or_else(|| anyhow::anyhow!("`{name}` returns unsupported types: {ty}"))?;
+
+    guest_call(store, |store| func.call(store, &params, &mut results))?;
+    Ok(vec![format!("{name}() -> ({})", format_vals(&results))])
+}
+
+fn no_gpu(wasm: &mut WASMRuntime) -> anyhow::Result<()> {
+    if wasm.store.get_mut().data().is_headless() {
+        anyhow::bail!("there is no GPU in terminal mode");
+    }
+    Ok(())
+}
+
+fn textures(wasm: &mut WASMRuntime) -> anyhow::Result<Vec<String>> {
+    no_gpu(wasm)?;
+    let entries = get_texture_registry().lock().entries();
+    let mut lines: Vec<String> = entries
+        .iter()
+        .map(|(id, texture)| {
+            let (width, height) = texture.with(|texture| (texture.width(), texture.height()));
+            format!("texture {id}: {width}x{height}")
+        })
+        .collect();
+    lines.push(format!("{} textures", entries.len()));
+    Ok(lines)
+}
+
+fn meshes(wasm: &mut WASMRuntime) -> anyhow::Result<Vec<String>> {
+    no_gpu(wasm)?;
+    let entries = get_mesh_registry().lock().entries();
+    let mut lines: Vec<String> = entries
+        .iter()
+        .map(|(id, mesh)| {
+            mesh.with(|mesh| {
+                format!(
+                    "mesh {id}: {:?}, {} vertices, {} indices",
+                    mesh.primitive_type,
+                    mesh.mesh.vertices.len(),
+                    mesh.mesh.indices.len()
+                )
+            })
+        })
+        .collect();
+    lines.push(format!("{} meshes", entries.len()));
+    Ok(lines)
+}
+
+fn speed(wasm: &mut WASMRuntime, args: &[&str]) -> anyhow::Result<Vec<String>> {
+    let clock = &mut wasm.store.get_mut().data_mut().clock;
+    if let Some(text) = args.first() {
+        let speed: f64 = text
+            .parse()
+            .with_context(|| format!("`{text}` is not a number"))?;
+        if !speed.is_finite() || speed < 0.0 {
+            anyhow::bail!("speed must be 0 or more");
+        }
+        clock.set_speed(speed);
+    }
+    Ok(vec![format!("clock speed is {}x", clock.speed())])
+}
+
+fn screenshot(wasm: &mut WASMRuntime, args: &[&str]) -> anyhow::Result<Vec<String>> {
+    let path = args
+        .first()
+        .map_or_else(|| timestamped_path("screenshot", "png"), PathBuf::from);
+    let resolution = wasm.store.get_mut().data().resolution;
+    let pixels = wasm.get_framebuffer()?;
+    let mut filtered = Vec::new();
+    let (width, height) = get_cli_args()
+        .filters
+        .filter_frame(&pixels, resolution, &mut filtered);
+
+    #[allow(clippy::cast_possible_truncation)]
+    image::save_buffer(
+        &path,
+        &filtered,
+        width as u32,
+        height as u32,
+        image::ColorType::Rgba8,
+    )
+    .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(vec![format!("saved screenshot to {}", path.display())])
+}
+
+/// The given path, or `<cartridge>.state` next to the cartridge.
+fn state_path(args: &[&str]) -> PathBuf {
+    args.first().map_or_else(
+        || get_cli_args().cartridge.with_extension("state"),
+        PathBuf::from,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_are_decimal_or_hex() {
+        assert_eq!(parse_number("42").unwrap(), 42);
+        assert_eq!(parse_number("0x1F").unwrap(), 31);
+        assert_eq!(parse_number("0xffffffffffffffff").unwrap(), u64::MAX);
+        for bad in ["", "0x", "-1", "12a", "0x1g", "0x10000000000000000"] {
+            assert!(parse_number(bad).is_err(), "{bad}");
+        }
+        assert_eq!(
+            parse_number("ten").err().unwrap().to_string(),
+            "`ten` is not a number"
+        );
+    }
+
+    #[test]
+    fn values_parse_by_type() {
+        let parse = |ty, text| parse_val(&ty, text);
+        assert_eq!(parse(ValType::I32, "-5").unwrap().i32(), Some(-5));
+        assert_eq!(parse(ValType::I32, "0xffffffff").unwrap().i32(), Some(-1));
+        assert!(parse(ValType::I32, "0x100000000").is_err());
+        assert!(parse(ValType::I32, "2147483648").is_err());
+        assert_eq!(
+            parse(ValType::I64, "0x8000000000000000").unwrap().i64(),
+            Some(i64::MIN)
+        );
+        assert_eq!(parse(ValType::I64, "-9").unwrap().i64(), Some(-9));
+        assert_eq!(parse(ValType::F32, "1.5").unwrap().f32(), Some(1.5));
+        assert_eq!(parse(ValType::F64, "-0.25").unwrap().f64(), Some(-0.25));
+        assert_eq!(
+            parse(ValType::F32, "x").err().unwrap().to_string(),
+            "`x` is not a valid f32"
+        );
+        assert!(parse(ValType::V128, "0").is_err());
+    }
+
+    #[test]
+    fn memory_ranges_are_bounds_checked() {
+        let mut mem = [1, 2, 3, 4];
+        assert_eq!(memory_range(&mut mem, 1, 2).unwrap(), &[2, 3]);
+        assert_eq!(memory_range(&mut mem, 4, 0).unwrap(), &[] as &[u8]);
+        assert!(memory_range(&mut mem, 3, 2).is_err());
+        assert!(memory_range(&mut mem, 5, 0).is_err());
+        assert!(memory_range(&mut mem, usize::MAX, 2).is_err());
+        assert!(memory_range(&mut mem, 2, usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+/// A rectangle of framebuffer pixels that changed since the last upload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+impl DirtyRect {
+    pub const fn full(width: usize, height: usize) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            w: width,
+            h: height,
+        }
+    }
+
+    /// Builds a rect from signed guest coordinates, clipped to the surface.
+    /// Returns `None` if nothing is left after clipping.
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
+    #[allow(clippy::cast_sign_loss)]
+    pub fn clipped(x: i32, y: i32, w: u32, h: u32, width: usize, height: usize) -> Option<Self> {
+        let left = i64::from(x).clamp(0, width as i64);
+        let top = i64::from(y).clamp(0, height as i64);
+        let right = (i64::from(x) + i64::from(w)).clamp(0, width as i64);
+        let bottom = (i64::from(y) + i64::from(h)).clamp(0, height as i64);
+
+        if left >= right || top >= bottom {
+            return None;
+        }
+
+        Some(Self {
+            x: left as usize,
+            y: top as usize,
+            w: (right - left) as usize,
+            h: (bottom - top) as usize,
+        })
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.w).max(other.x + other.w);
+        let bottom = (self.y + self.h).max(other.y + other.h);
+
+        Self {
+            x,
+            y,
+            w: right - x,
+            h: bottom - y,
+        }
+    }
+
+    pub const fn pixels(&self) -> usize {
+        self.w * self.h
+    }
+}
+
+/// Copies every row of `src` that differs from `dst` into `dst`, returning
+/// the bounding rect of the pixels that changed.
+pub fn sync_changed_rows(
+    src: &[u8],
+    dst: &mut [u8],
+    width: usize,
+    height: usize,
+) -> Option<DirtyRect> {
+    let stride = width * 4;
+    let mut dirty: Option<DirtyRect> = None;
+
+    for (y, (src_row, dst_row)) in src
+        .chunks_exact(stride)
+        .zip(dst.chunks_exact_mut(stride))
+        .take(height)
+        .enumerate()
+    {
+        if src_row == dst_row {
+            continue;
+        }
+
+        let differs = |(s, d): (&[u8], &[u8])| s != d;
+        let pixels = || src_row.chunks_exact(4).zip(dst_row.chunks_exact(4));
+        let first = pixels().position(differs).unwrap_or(0);
+        let last = width - 1 - pixels().rev().position(differs).unwrap_or(0);
+
+        dst_row.copy_from_slice(src_row);
+
+        let row = DirtyRect {
+            x: first,
+            y,
+            w: last - first + 1,
+            h: 1,
+        };
+        dirty = Some(dirty.map_or(row, |d| d.union(row)));
+    }
+
+    dirty
+}
+
+/// Copies the pixels inside `rect` from `src` into `dst`.
+pub fn copy_rect(src: &[u8], dst: &mut [u8], width: usize, rect: DirtyRect) {
+    for y in rect.y..rect.y + rect.h {
+        let start = (y * width + rect.x) * 4;
+        let end = start + rect.w * 4;
+        dst[start..end].copy_from_slice(&src[start..end]);
+    }
+}
+
+/// Packs the pixels inside `rect` tightly into `out`, ready for a partial
+/// texture upload.
+pub fn extract_rect(buf: &[u8], width: usize, rect: DirtyRect, out: &mut Vec<u8>) {
+    out.clear();
+    for y in rect.y..rect.y + rect.h {
+        let start = (y * width + rect.x) * 4;
+        out.extend_from_slice(&buf[start..start + rect.w * 4]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn rect(x: usize, y: usize, w: usize, h: usize) -> DirtyRect {
+        DirtyRect { x, y, w, h }
+    }
+
+    #[test]
+    fn clipped_keeps_the_visible_part() {
+        assert_eq!(
+            DirtyRect::clipped(2, 3, 4, 5, 10, 10),
+            Some(rect(2, 3, 4, 5))
+        );
+        assert_eq!(
+            DirtyRect::clipped(-2, -1, 4, 3, 10, 10),
+            Some(rect(0, 0, 2, 2))
+        );
+        assert_eq!(
+            DirtyRect::clipped(8, 9, 4, 4, 10, 10),
+            Some(rect(8, 9, 2, 1))
+        );
+        assert_eq!(
+            DirtyRect::clipped(i32::MIN, i32::MIN, u32::MAX, u32::MAX, 10, 10),
+            Some(rect(0, 0, 10, 10))
+        );
+        assert_eq!(DirtyRect::clipped(i32::MAX, 0, u32::MAX, 1, 10, 10), None);
+        assert_eq!(
+            DirtyRect::clipped(-5, -5, u32::MAX, u32::MAX, 10, 10),
+            Some(rect(0, 0, 10, 10))
+        );
+    }
+
+    #[test]
+    fn clipped_drops_empty_and_outside_rects() {
+        assert_eq!(DirtyRect::clipped(2, 2, 0, 4, 10, 10), None);
+        assert_eq!(DirtyRect::clipped(2, 2, 4, 0, 10, 10), None);
+        assert_eq!(DirtyRect::clipped(10, 0, 4, 4, 10, 10), None);
+        assert_eq!(DirtyRect::clipped(-4, 0, 4, 4, 10, 10), None);
+        assert_eq!(DirtyRect::clipped(0, 0, 4, 4, 0, 0), None);
+    }
+
+    #[test]
+    fn union_covers_both() {
+        assert_eq!(rect(1, 1, 2, 2).union(rect(5, 0, 1, 1)), rect(1, 0, 5, 3));
+        assert_eq!(rect(0, 0, 4, 4).union(rect(1, 1, 1, 1)), rect(0, 0, 4, 4));
+        assert_eq!(rect(3, 3, 1, 1).pixels(), 1);
+    }
+
+    #[test]
+    fn sync_reports_only_changed_pixels() {
+        let mut src = vec![0u8; 4 * 3 * 4];
+        let mut dst = src.clone();
+        assert_eq!(sync_changed_rows(&src, &mut dst, 4, 3), None);
+
+        // pixels 1 and 2 of row 0, and pixel 3 of row 2
+        src[4] = 1;
+        src[2 * 4 + 3] = 1;
+        src[(2 * 4 + 3) * 4] = 1;
+        assert_eq!(
+            sync_changed_rows(&src, &mut dst, 4, 3),
+            Some(rect(1, 0, 3, 3))
+        );
+        assert_eq!(src, dst);
+        assert_eq!(sync_changed_rows(&src, &mut dst, 4, 3), None);
+    }
+
+    #[test]
+    fn copy_and_extract_stay_inside_the_rect() {
+        let src: Vec<u8> = (0..3 * 2 * 4).collect();
+        let mut dst = vec![0u8; src.len()];
+        let inner = rect(1, 1, 2, 1);
+        copy_rect(&src, &mut dst, 3, inner);
+        assert_eq!(&dst[..16], &[0; 16]);
+        assert_eq!(&dst[16..], &src[16..]);
+
+        let mut out = Vec::new();
+        extract_rect(&src, 3, inner, &mut out);
+        assert_eq!(out, &src[16..]);
+    }
+}
@@ -0,0 +1,105 @@
+use macroquad::prelude::*;
+
+use crate::{
+    cartridge::Resolution,
+    dirty_region::{DirtyRect, extract_rect},
+    filters::FilterPipeline,
+    profiler::profiler_counter_add,
+};
+
+/// The texture the framebuffer is shown through, and how it gets there.
+pub struct Display {
+    resolution: Resolution,
+    filters: FilterPipeline,
+    texture: Texture2D,
+    /// Scratch space for partial uploads.
+    dirty_image: Image,
+    filtered: Vec<u8>,
+}
+
+impl Display {
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn new(resolution: Resolution, filters: FilterPipeline) -> Self {
+        let (width, height) = filters.output_size(
+            resolution.width as usize,
+            resolution.height as usize,
+            resolution.scale() as usize,
+        );
+        let texture =
+            Texture2D::from_rgba8(width as u16, height as u16, &vec![0u8; width * height * 4]);
+
+        Self {
+            resolution,
+            filters,
+            texture,
+            dirty_image: Image::empty(),
+            filtered: Vec::new(),
+        }
+    }
+
+    /// Uploads the parts of `pixels` inside `dirty`. Filters can move pixels
+    /// around, so with any enabled the whole filtered frame is uploaded.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn upload(&mut self, pixels: &[u8], dirty: Option<DirtyRect>) {
+        let Some(rect) = dirty else {
+            return;
+        };
+        let width = self.resolution.width as usize;
+        let height = self.resolution.height as usize;
+
+        if self.filters.is_active() {
+            let (out_w, out_h) =
+                self.filters
+                    .filter_frame(pixels, self.resolution, &mut self.filtered);
+
+            #[allow(clippy::cast_precision_loss)]
+            profiler_counter_add("fb dirty pixels", (out_w * out_h) as f64);
+            self.texture
+                .update_from_bytes(out_w as u32, out_h as u32, &self.filtered);
+            return;
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        profiler_counter_add("fb dirty pixels", rect.pixels() as f64);
+        if rect == DirtyRect::full(width, height) {
+            self.texture
+                .update_from_bytes(self.resolution.width, self.resolution.height, pixels);
+        } else {
+            self.upload_dirty_rect(pixels, width, rect);
+        }
+    }
+
+    /// Uploads only the pixels inside `rect`, using `dirty_image` as scratch
+    /// space.
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
+    fn upload_dirty_rect(&mut self, pixels: &[u8], width: usize, rect: DirtyRect) {
+        let image = &mut self.dirty_image;
+        extract_rect(pixels, width, rect, &mut image.bytes);
+        image.width = rect.w as u16;
+        image.height = rect.h as u16;
+        self.texture.update_part(
+            image,
+            rect.x as i32,
+            rect.y as i32,
+            rect.w as i32,
+            rect.h as i32,
+        );
+    }
+
+    /// Draws the framebuffer texture scaled up to fill the window.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn draw(&self) {
+        let (width, height) = self.resolution.window_size();
+        draw_texture_ex(
+            &self.texture,
+            0.0,
+            0.0,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(width as f32, height as f32)),
+                ..Default::default()
+            },
+        );
+    }
+}
@@ -0,0 +1,446 @@
+//! Display filters applied on the CPU to the finished frame, between reading
+//! the guest framebuffer and uploading it.
+
+use std::{str::FromStr, sync::OnceLock};
+
+use crate::cartridge::Resolution;
+
+/// How dark scanlines are when no intensity is given.
+const DEFAULT_SCANLINE_INTENSITY: f32 = 0.5;
+
+/// Classic four-shade handheld greens, darkest first.
+const GAMEBOY_PALETTE: [[u8; 3]; 4] = [
+    [0x0f, 0x38, 0x0f],
+    [0x30, 0x62, 0x30],
+    [0x8b, 0xac, 0x0f],
+    [0x9b, 0xbc, 0x0f],
+];
+
+const GRAYSCALE_PALETTE: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x00],
+    [0x55, 0x55, 0x55],
+    [0xaa, 0xaa, 0xaa],
+    [0xff, 0xff, 0xff],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorBlindness {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl ColorBlindness {
+    /// Simulation matrices for linear RGB from Machado, Oliveira and Fernandes
+    /// (2009), at full severity.
+    const fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Self::Protanopia => [
+                [0.152_286, 1.052_583, -0.204_868],
+                [0.114_503, 0.786_281, 0.099_216],
+                [-0.003_882, -0.048_116, 1.051_998],
+            ],
+            Self::Deuteranopia => [
+                [0.367_322, 0.860_646, -0.227_968],
+                [0.280_085, 0.672_501, 0.047_413],
+                [-0.011_820, 0.042_940, 0.968_881],
+            ],
+            Self::Tritanopia => [
+                [1.255_528, -0.076_749, -0.178_779],
+                [-0.078_411, 0.930_809, 0.147_602],
+                [0.004_733, 0.691_367, 0.303_900],
+            ],
+        }
+    }
+}
+
+impl FromStr for ColorBlindness {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "protanopia" => Ok(Self::Protanopia),
+            "deuteranopia" => Ok(Self::Deuteranopia),
+            "tritanopia" => Ok(Self::Tritanopia),
+            _ => anyhow::bail!("unknown color blindness `{s}`"),
+        }
+    }
+}
+
+/// The filters to run, in the order they are applied: palette, color
+/// blindness, scaling, scanlines.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FilterPipeline {
+    /// Shades ordered dark to light; pixels are mapped by brightness.
+    pub palette: Option<Vec<[u8; 3]>>,
+    pub color_blindness: Option<ColorBlindness>,
+    /// Bilinear instead of nearest neighbour upscaling.
+    pub smooth: bool,
+    /// How much scanlines darken, from 0 to 1.
+    pub scanlines: Option<f32>,
+}
+
+impl FilterPipeline {
+    pub const fn is_active(&self) -> bool {
+        self.palette.is_some()
+            || self.color_blindness.is_some()
+            || self.smooth
+            || self.scanlines.is_some()
+    }
+
+    /// Scaling and scanlines happen at window resolution; the other filters
+    /// leave scaling to the GPU.
+    pub const fn scales(&self) -> bool {
+        self.smooth || self.scanlines.is_some()
+    }
+
+    pub const fn output_size(&self, width: usize, height: usize, scale: usize) -> (usize, usize) {
+        if self.scales() {
+            (width * scale, height * scale)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Filters a `width` x `height` frame into `out`, which ends up
+    /// [`Self::output_size`] big.
+    pub fn apply(&self, src: &[u8], width: usize, height: usize, scale: usize, out: &mut Vec<u8>) {
+        let mut frame = src.to_vec();
+
+        if let Some(palette) = &self.palette {
+            quantize(&mut frame, palette);
+        }
+        if let Some(color_blindness) = self.color_blindness {
+            simulate_color_blindness(&mut frame, color_blindness);
+        }
+
+        if !self.scales() {
+            *out = frame;
+            return;
+        }
+
+        let scale = scale.max(1);
+        if self.smooth {
+            scale_bilinear(&frame, width, height, scale, out);
+        } else {
+            scale_nearest(&frame, width, height, scale, out);
+        }
+        if let Some(intensity) = self.scanlines {
+            darken_scanlines(out, width * scale, height * scale, scale, intensity);
+        }
+    }
+
+    /// Filters a finished frame at `resolution` into `out` and returns the
+    /// size it ends up. The window, the terminal and screenshots all go
+    /// through here.
+    pub fn filter_frame(
+        &self,
+        pixels: &[u8],
+        resolution: Resolution,
+        out: &mut Vec<u8>,
+    ) -> (usize, usize) {
+        let width = resolution.width as usize;
+        let height = resolution.height as usize;
+        let scale = resolution.scale() as usize;
+        self.apply(pixels, width, height, scale, out);
+        self.output_size(width, height, scale)
+    }
+}
+
+impl FromStr for FilterPipeline {
+    type Err = anyhow::Error;
+
+    /// Parses a comma separated list like
+    /// `scanlines=0.3,smooth,palette=gameboy,colorblind=deuteranopia`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut pipeline = Self::default();
+
+        for filter in s.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let (name, value) = filter
+                .split_once('=')
+                .map_or((filter, None), |(name, value)| (name, Some(value)));
+
+            match (name, value) {
+                ("smooth", None) => pipeline.smooth = true,
+                ("nearest", None) => pipeline.smooth = false,
+                ("scanlines", None) => pipeline.scanlines = Some(DEFAULT_SCANLINE_INTENSITY),
+                ("scanlines", Some(value)) => {
+                    let intensity: f32 = value.parse()?;
+                    if !(0.0..=1.0).contains(&intensity) {
+                        anyhow::bail!("scanline intensity must be between 0 and 1");
+                    }
+                    pipeline.scanlines = Some(intensity);
+                }
+                ("palette", Some(value)) => pipeline.palette = Some(parse_palette(value)?),
+                ("colorblind", Some(value)) => pipeline.color_blindness = Some(value.parse()?),
+                _ => anyhow::bail!("unknown filter `{filter}`"),
+            }
+        }
+
+        Ok(pipeline)
+    }
+}
+
+/// A named palette, or `:` separated hex colors like `#000000:#ffffff`.
+fn parse_palette(s: &str) -> anyhow::Result<Vec<[u8; 3]>> {
+    match s {
+        "gameboy" => return Ok(GAMEBOY_PALETTE.to_vec()),
+        "grayscale" => return Ok(GRAYSCALE_PALETTE.to_vec()),
+        _ => {}
+    }
+
+    let palette = s
+        .split(':')
+        .map(|color| {
+            let hex = color.trim_start_matches('#');
+            let value = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)
+                .ok_or_else(|| anyhow::anyhow!("bad palette color `{color}`"))?;
+            let [_, r, g, b] = value.to_be_bytes();
+            Ok([r, g, b])
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if palette.len() < 2 {
+        anyhow::bail!("a palette needs at least two colors");
+    }
+    Ok(palette)
+}
+
+/// Rec. 601 luma, 0 to 255.
+fn luma(pixel: &[u8]) -> u32 {
+    (299 * u32::from(pixel[0]) + 587 * u32::from(pixel[1]) + 114 * u32::from(pixel[2])) / 1000
+}
+
+fn quantize(frame: &mut [u8], palette: &[[u8; 3]]) {
+    for pixel in frame.chunks_exact_mut(4) {
+        let shade = luma(pixel) as usize * palette.len() / 256;
+        pixel[..3].copy_from_slice(&palette[shade]);
+    }
+}
+
+fn srgb_to_linear_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0.0; 256];
+        for (value, entry) in (0u8..=255).zip(table.iter_mut()) {
+            let c = f32::from(value) / 255.0;
+            *entry = if c <= 0.040_45 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+        }
+        table
+    })
+}
+
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055f32.mul_add(c.powf(1.0 / 2.4), -0.055)
+    };
+    c.mul_add(255.0, 0.5) as u8
+}
+
+fn simulate_color_blindness(frame: &mut [u8], color_blindness: ColorBlindness) {
+    let to_linear = srgb_to_linear_table();
+    let matrix = color_blindness.matrix();
+
+    for pixel in frame.chunks_exact_mut(4) {
+        let rgb = [
+            to_linear[usize::from(pixel[0])],
+            to_linear[usize::from(pixel[1])],
+            to_linear[usize::from(pixel[2])],
+        ];
+        for (out, row) in pixel.iter_mut().zip(matrix) {
+            let c = row[0].mul_add(rgb[0], row[1].mul_add(rgb[1], row[2] * rgb[2]));
+            *out = linear_to_srgb(c);
+        }
+    }
+}
+
+fn scale_nearest(frame: &[u8], width: usize, height: usize, scale: usize, out: &mut Vec<u8>) {
+    out.clear();
+    out.reserve(frame.len() * scale * scale);
+
+    for row in frame.chunks_exact(width * 4).take(height) {
+        let start = out.len();
+        for pixel in row.chunks_exact(4) {
+            for _ in 0..scale {
+                out.extend_from_slice(pixel);
+            }
+        }
+        let scaled_row = out.len() - start;
+        for _ in 1..scale {
+            out.extend_from_within(start..start + scaled_row);
+        }
+    }
+}
+
+/// Bilinear upscale sampling at output pixel centers, clamped at the edges.
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::cast_sign_loss)]
+fn scale_bilinear(frame: &[u8], width: usize, height: usize, scale: usize, out: &mut Vec<u8>) {
+    let (out_w, out_h) = (width * scale, height * scale);
+    out.clear();
+    out.resize(out_w * out_h * 4, 0);
+
+    // source coordinate and blend weight for every output column/row
+    let taps = |size: usize| -> Vec<(usize, usize, f32)> {
+        (0..size * scale)
+            .map(|i| {
+                let pos = ((i as f32 + 0.5) / scale as f32 - 0.5).max(0.0);
+                let first = (pos as usize).min(size - 1);
+                let second = (first + 1).min(size - 1);
+                (first, second, pos - first as f32)
+            })
+            .collect()
+    };
+    let columns = taps(width);
+    let rows = taps(height);
+
+    let pixel =
+        |x: usize, y: usize, channel: usize| f32::from(frame[(y * width + x) * 4 + channel]);
+    for (oy, &(y0, y1, ty)) in rows.iter().enumerate() {
+        for (ox, &(x0, x1, tx)) in columns.iter().enumerate() {
+            let o = (oy * out_w + ox) * 4;
+            for channel in 0..4 {
+                let top = (pixel(x1, y0, channel) - pixel(x0, y0, channel))
+                    .mul_add(tx, pixel(x0, y0, channel));
+                let bottom = (pixel(x1, y1, channel) - pixel(x0, y1, channel))
+                    .mul_add(tx, pixel(x0, y1, channel));
+                out[o + channel] = (bottom - top).mul_add(ty, top).round() as u8;
+            }
+        }
+    }
+}
+
+/// Darkens the last output row of every source row, or every other row when
+/// the frame is not scaled up.
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn darken_scanlines(frame: &mut [u8], width: usize, height: usize, scale: usize, intensity: f32) {
+    let period = scale.max(2);
+    let keep = 1.0 - intensity;
+
+    for (y, row) in frame.chunks_exact_mut(width * 4).take(height).enumerate() {
+        if y % period != period - 1 {
+            continue;
+        }
+        for pixel in row.chunks_exact_mut(4) {
+            for c in &mut pixel[..3] {
+                *c = (f32::from(*c) * keep).round() as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(pixels: &[[u8; 4]]) -> Vec<u8> {
+        pixels.iter().flatten().copied().collect()
+    }
+
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    #[test]
+    fn no_filters_is_identity() {
+        let frame = image(&[BLACK, WHITE, [10, 20, 30, 255], WHITE]);
+        let mut out = Vec::new();
+        FilterPipeline::default().apply(&frame, 2, 2, 3, &mut out);
+        assert_eq!(out, frame);
+    }
+
+    #[test]
+    fn frames_are_filtered_at_the_window_scale() {
+        let resolution = Resolution {
+            width: 320,
+            height: 240,
+        };
+        let frame = vec![255; resolution.pixels() * 4];
+        let mut out = Vec::new();
+
+        let filters: FilterPipeline = "scanlines".parse().unwrap();
+        assert_eq!(
+            filters.filter_frame(&frame, resolution, &mut out),
+            (640, 480)
+        );
+        assert_eq!(out.len(), 640 * 480 * 4);
+
+        let filters: FilterPipeline = "palette=grayscale".parse().unwrap();
+        assert_eq!(
+            filters.filter_frame(&frame, resolution, &mut out),
+            (320, 240)
+        );
+        assert_eq!(out, frame);
+    }
+
+    #[test]
+    fn nearest_scanlines_match_expected_image() {
+        let filters: FilterPipeline = "scanlines=0.5".parse().unwrap();
+        let frame = image(&[BLACK, WHITE]);
+        let mut out = Vec::new();
+        filters.apply(&frame, 2, 1, 2, &mut out);
+
+        let grey = [128, 128, 128, 255];
+        assert_eq!(
+            out,
+            image(&[BLACK, BLACK, WHITE, WHITE, BLACK, BLACK, grey, grey])
+        );
+    }
+
+    #[test]
+    fn smooth_scaling_blends_neighbours() {
+        let filters: FilterPipeline = "smooth".parse().unwrap();
+        let frame = image(&[BLACK, WHITE]);
+        let mut out = Vec::new();
+        filters.apply(&frame, 2, 1, 2, &mut out);
+
+        let row = image(&[BLACK, [64, 64, 64, 255], [191, 191, 191, 255], WHITE]);
+        assert_eq!(out, [row.clone(), row].concat());
+    }
+
+    #[test]
+    fn gameboy_palette_maps_by_brightness() {
+        let filters: FilterPipeline = "palette=gameboy".parse().unwrap();
+        let frame = image(&[BLACK, [90, 90, 90, 255], [170, 170, 170, 255], WHITE]);
+        let mut out = Vec::new();
+        filters.apply(&frame, 4, 1, 1, &mut out);
+
+        let shades: Vec<[u8; 4]> = GAMEBOY_PALETTE
+            .iter()
+            .map(|&[r, g, b]| [r, g, b, 255])
+            .collect();
+        assert_eq!(out, image(&shades));
+    }
+
+    #[test]
+    fn color_blindness_keeps_greys_and_merges_red_green() {
+        let filters: FilterPipeline = "colorblind=deuteranopia".parse().unwrap();
+        let frame = image(&[BLACK, WHITE, [255, 0, 0, 255], [0, 255, 0, 255]]);
+        let mut out = Vec::new();
+        filters.apply(&frame, 4, 1, 1, &mut out);
+
+        assert_eq!(&out[..8], &image(&[BLACK, WHITE])[..]);
+        let (red, green) = (&out[8..11], &out[12..15]);
+        assert!(red[0].abs_diff(red[1]) < 64 && green[0].abs_diff(green[1]) < 64);
+    }
+
+    #[test]
+    fn parses_custom_palette_and_rejects_unknown_filters() {
+        let filters: FilterPipeline = "palette=#000000:#ff8000".parse().unwrap();
+        assert_eq!(filters.palette, Some(vec![[0, 0, 0], [0xff, 0x80, 0]]));
+        assert!("sepia".parse::<FilterPipeline>().is_err());
+        assert!("scanlines=2".parse::<FilterPipeline>().is_err());
+    }
+}
@@ -0,0 +1,501 @@
+//! Bitmap fonts for the `text` host functions.
+
+use macroquad::prelude::*;
+use parking_lot::Mutex;
+use std::sync::OnceLock;
+
+use crate::{
+    cartridge::Resolution,
+    surface::{Surface, tinted},
+};
+
+pub type FontId = u32;
+
+const BUILTIN_GLYPH_WIDTH: usize = 7;
+const BUILTIN_GLYPH_HEIGHT: usize = 12;
+const BUILTIN_FIRST_CHAR: u32 = ' ' as u32;
+
+/// Drawn in place of characters a font has no glyph for.
+const REPLACEMENT_CHAR: char = '?';
+
+enum Glyphs {
+    /// One row bitmask per line of [`BUILTIN_GLYPHS`], leftmost pixel in the
+    /// low bit.
+    Builtin,
+    /// Premultiplied RGBA atlas with glyphs laid out left to right, top to
+    /// bottom.
+    Atlas {
+        pixels: Vec<u8>,
+        width: usize,
+        columns: usize,
+    },
+}
+
+/// A monospaced font where every glyph is a `glyph_w` x `glyph_h` cell.
+pub struct BitmapFont {
+    glyph_w: usize,
+    glyph_h: usize,
+    first: u32,
+    count: u32,
+    glyphs: Glyphs,
+}
+
+impl BitmapFont {
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn builtin() -> Self {
+        Self {
+            glyph_w: BUILTIN_GLYPH_WIDTH,
+            glyph_h: BUILTIN_GLYPH_HEIGHT,
+            first: BUILTIN_FIRST_CHAR,
+            count: BUILTIN_GLYPHS.len() as u32,
+            glyphs: Glyphs::Builtin,
+        }
+    }
+
+    /// A font cut from a `width` x `height` atlas holding `count` glyphs for
+    /// the characters starting at `first`.
+    pub fn from_atlas(
+        pixels: Vec<u8>,
+        width: usize,
+        height: usize,
+        glyph_w: usize,
+        glyph_h: usize,
+        first: u32,
+        count: u32,
+    ) -> anyhow::Result<Self> {
+        if glyph_w == 0 || glyph_h == 0 || glyph_w > width || glyph_h > height {
+            anyhow::bail!("glyph size {glyph_w}x{glyph_h} does not fit a {width}x{height} atlas");
+        }
+        let columns = width / glyph_w;
+        let capacity = columns * (height / glyph_h);
+        if count as usize > capacity {
+            anyhow::bail!("atlas only has room for {capacity} glyphs, not {count}");
+        }
+
+        Ok(Self {
+            glyph_w,
+            glyph_h,
+            first,
+            count,
+            glyphs: Glyphs::Atlas {
+                pixels,
+                width,
+                columns,
+            },
+        })
+    }
+
+    fn glyph_index(&self, c: char) -> Option<usize> {
+        let index = u32::from(c).checked_sub(self.first)?;
+        (index < self.count).then_some(index as usize)
+    }
+
+    /// The glyph's pixel at `x`, `y` as premultiplied RGBA, before tinting.
+    fn glyph_pixel(&self, index: usize, x: usize, y: usize) -> [u8; 4] {
+        match &self.glyphs {
+            Glyphs::Builtin => {
+                if BUILTIN_GLYPHS[index][y] & (1 << x) != 0 {
+                    [255; 4]
+                } else {
+                    [0; 4]
+                }
+            }
+            Glyphs::Atlas {
+                pixels,
+                width,
+                columns,
+            } => {
+                let px = (index % columns) * self.glyph_w + x;
+                let py = (index / columns) * self.glyph_h + y;
+                let i = (py * width + px) * 4;
+                pixels[i..i + 4].try_into().unwrap()
+            }
+        }
+    }
+
+    /// Size of `text` in pixels. Lines are split on `\n`.
+    pub fn measure(&self, text: &str, scale: u32) -> (u32, u32) {
+        let scale = scale as usize;
+        let lines = text.split('\n');
+        let columns = lines.clone().map(|line| line.chars().count()).max();
+        let rows = lines.count();
+
+        let width = columns
+            .unwrap_or(0)
+            .saturating_mul(self.glyph_w)
+            .saturating_mul(scale);
+        let height = rows.saturating_mul(self.glyph_h).saturating_mul(scale);
+        (
+            u32::try_from(width).unwrap_or(u32::MAX),
+            u32::try_from(height).unwrap_or(u32::MAX),
+        )
+    }
+
+    /// Draws `text` with its top left corner at `x`, `y`, every glyph pixel
+    /// scaled up to a `scale` x `scale` block and multiplied by `color`.
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        surface: &mut Surface,
+        text: &str,
+        x: i32,
+        y: i32,
+        color: u32,
+        scale: u32,
+        blend: bool,
+    ) {
+        if scale == 0 {
+            return;
+        }
+        let tint = color.to_le_bytes();
+        // in i64, as the cartridge picks the scale
+        let step_x = self.glyph_w as i64 * i64::from(scale);
+        let step_y = self.glyph_h as i64 * i64::from(scale);
+
+        for (row, line) in (0i64..).zip(text.split('\n')) {
+            let top = i64::from(y) + row * step_y;
+            for (column, c) in (0i64..).zip(line.chars()) {
+                let left = i64::from(x) + column * step_x;
+                let Some(index) = self
+                    .glyph_index(c)
+                    .or_else(|| self.glyph_index(REPLACEMENT_CHAR))
+                else {
+                    continue;
+                };
+
+                for gy in 0..self.glyph_h {
+                    for gx in 0..self.glyph_w {
+                        let pixel = tinted(&self.glyph_pixel(index, gx, gy), tint);
+                        if pixel[3] == 0 {
+                            continue;
+                        }
+                        let (Ok(px), Ok(py)) = (
+                            i32::try_from(left + gx as i64 * i64::from(scale)),
+                            i32::try_from(top + gy as i64 * i64::from(scale)),
+                        ) else {
+                            continue;
+                        };
+                        surface.fill_rect(px, py, scale, scale, u32::from_le_bytes(pixel), blend);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Registered fonts, indexed by [`FontId`]. Id 0 is the built-in font.
+pub struct FontRegistry {
+    fonts: Vec<BitmapFont>,
+}
+
+impl FontRegistry {
+    pub fn new() -> Self {
+        Self {
+            fonts: vec![BitmapFont::builtin()],
+        }
+    }
+
+    pub fn register(&mut self, font: BitmapFont) -> FontId {
+        self.fonts.push(font);
+        let id = FontId::try_from(self.fonts.len() - 1).unwrap();
+        log::info!("registered font with id {id}");
+        id
+    }
+
+    pub fn find_font(&self, id: FontId) -> Option<&BitmapFont> {
+        self.fonts.get(id as usize)
+    }
+}
+
+/// Text the cartridge asked to have drawn over the finished frame, in
+/// framebuffer coordinates.
+pub struct QueuedText {
+    pub x: i32,
+    pub y: i32,
+    pub text: String,
+    pub color: u32,
+    pub scale: u32,
+    pub font: FontId,
+}
+
+/// Draws text queued through `text::queue_text` on top of the frame, scaled
+/// along with the framebuffer.
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_precision_loss)]
+pub fn draw_queued_text(queued: &[QueuedText], resolution: Resolution) {
+    let fonts = get_font_registry().lock();
+    let window_scale = resolution.scale() as f32;
+
+    for item in queued {
+        let Some(font) = fonts.find_font(item.font) else {
+            log::error!("font with id {} doesn't exist!", item.font);
+            continue;
+        };
+        let (width, height) = font.measure(&item.text, item.scale);
+        if width == 0 || height == 0 || width > u32::from(u16::MAX) || height > u32::from(u16::MAX)
+        {
+            continue;
+        }
+
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+        let mut surface = Surface {
+            pixels: &mut pixels,
+            width: width as usize,
+            height: height as usize,
+        };
+        font.draw(
+            &mut surface,
+            &item.text,
+            0,
+            0,
+            item.color,
+            item.scale,
+            false,
+        );
+
+        let texture = Texture2D::from_rgba8(width as u16, height as u16, &pixels);
+        draw_texture_ex(
+            &texture,
+            item.x as f32 * window_scale,
+            item.y as f32 * window_scale,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(
+                    width as f32 * window_scale,
+                    height as f32 * window_scale,
+                )),
+                ..Default::default()
+            },
+        );
+    }
+    drop(fonts);
+}
+
+pub fn get_font_registry() -> &'static Mutex<FontRegistry> {
+    static FONT_REGISTRY: OnceLock<Mutex<FontRegistry>> = OnceLock::new();
+    FONT_REGISTRY.get_or_init(|| Mutex::new(FontRegistry::new()))
+}
+
+/// Printable ASCII, rasterized from `ProggyClean` (MIT, Tristan Grimmer), the
+/// pixel font macroquad ships with.
+#[rustfmt::skip]
+const BUILTIN_GLYPHS: [[u8; BUILTIN_GLYPH_HEIGHT]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x00, 0x08, 0x00, 0x00, 0x00], // '!'
+    [0x14, 0x14, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x00, 0x28, 0x28, 0x7e, 0x14, 0x14, 0x3f, 0x0a, 0x0a, 0x00, 0x00, 0x00], // '#'
+    [0x00, 0x08, 0x3c, 0x0a, 0x0a, 0x1c, 0x28, 0x28, 0x1e, 0x08, 0x00, 0x00], // '$'
+    [0x00, 0x22, 0x15, 0x15, 0x0a, 0x28, 0x54, 0x54, 0x22, 0x00, 0x00, 0x00], // '%'
+    [0x00, 0x0c, 0x12, 0x12, 0x4c, 0x52, 0x22, 0x22, 0x5c, 0x00, 0x00, 0x00], // '&'
+    [0x08, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x10, 0x08, 0x08, 0x04, 0x04, 0x04, 0x04, 0x04, 0x08, 0x08, 0x10, 0x00], // '('
+    [0x04, 0x08, 0x08, 0x10, 0x10, 0x10, 0x10, 0x10, 0x08, 0x08, 0x04, 0x00], // ')'
+    [0x00, 0x00, 0x00, 0x08, 0x2a, 0x1c, 0x2a, 0x08, 0x00, 0x00, 0x00, 0x00], // '*'
+    [0x00, 0x00, 0x00, 0x08, 0x08, 0x3e, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x04, 0x04, 0x02, 0x00], // ','
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x04, 0x00, 0x00, 0x00], // '.'
+    [0x20, 0x20, 0x10, 0x10, 0x08, 0x08, 0x04, 0x04, 0x02, 0x02, 0x00, 0x00], // '/'
+    [0x00, 0x1c, 0x22, 0x22, 0x2a, 0x2a, 0x22, 0x22, 0x1c, 0x00, 0x00, 0x00], // '0'
+    [0x00, 0x08, 0x0c, 0x0a, 0x08, 0x08, 0x08, 0x08, 0x3e, 0x00, 0x00, 0x00], // '1'
+    [0x00, 0x1c, 0x22, 0x20, 0x10, 0x08, 0x04, 0x02, 0x3e, 0x00, 0x00, 0x00], // '2'
+    [0x00, 0x1c, 0x22, 0x20, 0x18, 0x20, 0x20, 0x22, 0x1c, 0x00, 0x00, 0x00], // '3'
+    [0x00, 0x20, 0x30, 0x28, 0x24, 0x22, 0x7e, 0x20, 0x20, 0x00, 0x00, 0x00], // '4'
+    [0x00, 0x3e, 0x02, 0x02, 0x1e, 0x20, 0x20, 0x22, 0x1c, 0x00, 0x00, 0x00], // '5'
+    [0x00, 0x18, 0x04, 0x02, 0x1e, 0x22, 0x22, 0x22, 0x1c, 0x00, 0x00, 0x00], // '6'
+    [0x00, 0x3e, 0x20, 0x10, 0x10, 0x08, 0x08, 0x04, 0x04, 0x00, 0x00, 0x00], // '7'
+    [0x00, 0x1c, 0x22, 0x22, 0x1c, 0x22, 0x22, 0x22, 0x1c, 0x00, 0x00, 0x00], // '8'
+    [0x00, 0x1c, 0x22, 0x22, 0x22, 0x3c, 0x20, 0x10, 0x0c, 0x00, 0x00, 0x00], // '9'
+    [0x00, 0x00, 0x00, 0x08, 0x08, 0x00, 0x00, 0x08, 0x08, 0x00, 0x00, 0x00], // ':'
+    [0x00, 0x00, 0x00, 0x04, 0x04, 0x00, 0x00, 0x04, 0x04, 0x04, 0x02, 0x00], // ';'
+    [0x00, 0x00, 0x00, 0x30, 0x0c, 0x03, 0x0c, 0x30, 0x00, 0x00, 0x00, 0x00], // '<'
+    [0x00, 0x00, 0x00, 0x00, 0x7e, 0x00, 0x7e, 0x00, 0x00, 0x00, 0x00, 0x00], // '='
+    [0x00, 0x00, 0x00, 0x06, 0x18, 0x60, 0x18, 0x06, 0x00, 0x00, 0x00, 0x00], // '>'
+    [0x00, 0x1c, 0x22, 0x20, 0x10, 0x08, 0x08, 0x00, 0x08, 0x00, 0x00, 0x00], // '?'
+    [0x00, 0x1c, 0x22, 0x59, 0x55, 0x55, 0x39, 0x02, 0x3c, 0x00, 0x00, 0x00], // '@'
+    [0x00, 0x18, 0x18, 0x24, 0x24, 0x3c, 0x42, 0x42, 0x42, 0x00, 0x00, 0x00], // 'A'
+    [0x00, 0x1e, 0x22, 0x22, 0x3e, 0x42, 0x42, 0x42, 0x3e, 0x00, 0x00, 0x00], // 'B'
+    [0x00, 0x38, 0x44, 0x02, 0x02, 0x02, 0x02, 0x44, 0x38, 0x00, 0x00, 0x00], // 'C'
+    [0x00, 0x1e, 0x22, 0x42, 0x42, 0x42, 0x42, 0x22, 0x1e, 0x00, 0x00, 0x00], // 'D'
+    [0x00, 0x3e, 0x02, 0x02, 0x1e, 0x02, 0x02, 0x02, 0x3e, 0x00, 0x00, 0x00], // 'E'
+    [0x00, 0x3e, 0x02, 0x02, 0x1e, 0x02, 0x02, 0x02, 0x02, 0x00, 0x00, 0x00], // 'F'
+    [0x00, 0x38, 0x44, 0x02, 0x02, 0x72, 0x42, 0x44, 0x38, 0x00, 0x00, 0x00], // 'G'
+    [0x00, 0x42, 0x42, 0x42, 0x7e, 0x42, 0x42, 0x42, 0x42, 0x00, 0x00, 0x00], // 'H'
+    [0x00, 0x1c, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x1c, 0x00, 0x00, 0x00], // 'I'
+    [0x00, 0x1c, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x0e, 0x00, 0x00, 0x00], // 'J'
+    [0x00, 0x42, 0x22, 0x12, 0x0a, 0x0e, 0x12, 0x22, 0x42, 0x00, 0x00, 0x00], // 'K'
+    [0x00, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x3e, 0x00, 0x00, 0x00], // 'L'
+    [0x00, 0x63, 0x63, 0x55, 0x55, 0x49, 0x49, 0x41, 0x41, 0x00, 0x00, 0x00], // 'M'
+    [0x00, 0x46, 0x46, 0x4a, 0x4a, 0x52, 0x52, 0x62, 0x62, 0x00, 0x00, 0x00], // 'N'
+    [0x00, 0x18, 0x24, 0x42, 0x42, 0x42, 0x42, 0x24, 0x18, 0x00, 0x00, 0x00], // 'O'
+    [0x00, 0x1e, 0x22, 0x22, 0x22, 0x1e, 0x02, 0x02, 0x02, 0x00, 0x00, 0x00], // 'P'
+    [0x00, 0x18, 0x24, 0x42, 0x42, 0x42, 0x42, 0x24, 0x58, 0x40, 0x00, 0x00], // 'Q'
+    [0x00, 0x1e, 0x22, 0x22, 0x22, 0x1e, 0x12, 0x22, 0x42, 0x00, 0x00, 0x00], // 'R'
+    [0x00, 0x3c, 0x42, 0x02, 0x0c, 0x30, 0x40, 0x42, 0x3c, 0x00, 0x00, 0x00], // 'S'
+    [0x00, 0x7f, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x00, 0x00, 0x00], // 'T'
+    [0x00, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x3c, 0x00, 0x00, 0x00], // 'U'
+    [0x00, 0x41, 0x41, 0x22, 0x22, 0x14, 0x14, 0x08, 0x08, 0x00, 0x00, 0x00], // 'V'
+    [0x00, 0x41, 0x49, 0x49, 0x55, 0x55, 0x36, 0x22, 0x22, 0x00, 0x00, 0x00], // 'W'
+    [0x00, 0x42, 0x42, 0x24, 0x18, 0x18, 0x24, 0x42, 0x42, 0x00, 0x00, 0x00], // 'X'
+    [0x00, 0x41, 0x41, 0x22, 0x14, 0x08, 0x08, 0x08, 0x08, 0x00, 0x00, 0x00], // 'Y'
+    [0x00, 0x7e, 0x40, 0x20, 0x10, 0x08, 0x04, 0x02, 0x7e, 0x00, 0x00, 0x00], // 'Z'
+    [0x1c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x1c, 0x00], // '['
+    [0x02, 0x02, 0x04, 0x04, 0x08, 0x08, 0x10, 0x10, 0x20, 0x20, 0x00, 0x00], // '\\'
+    [0x1c, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1c, 0x00], // ']'
+    [0x08, 0x08, 0x14, 0x14, 0x22, 0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7f, 0x00, 0x00], // '_'
+    [0x04, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x00, 0x1c, 0x20, 0x3c, 0x22, 0x22, 0x3c, 0x00, 0x00, 0x00], // 'a'
+    [0x02, 0x02, 0x02, 0x1e, 0x22, 0x22, 0x22, 0x22, 0x1e, 0x00, 0x00, 0x00], // 'b'
+    [0x00, 0x00, 0x00, 0x1c, 0x22, 0x02, 0x02, 0x22, 0x1c, 0x00, 0x00, 0x00], // 'c'
+    [0x20, 0x20, 0x20, 0x3c, 0x22, 0x22, 0x22, 0x22, 0x3c, 0x00, 0x00, 0x00], // 'd'
+    [0x00, 0x00, 0x00, 0x1c, 0x22, 0x3e, 0x02, 0x22, 0x1c, 0x00, 0x00, 0x00], // 'e'
+    [0x38, 0x04, 0x04, 0x1e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x00, 0x00], // 'f'
+    [0x00, 0x00, 0x00, 0x3c, 0x22, 0x22, 0x22, 0x22, 0x3c, 0x20, 0x20, 0x1c], // 'g'
+    [0x02, 0x02, 0x02, 0x1e, 0x22, 0x22, 0x22, 0x22, 0x22, 0x00, 0x00, 0x00], // 'h'
+    [0x08, 0x00, 0x00, 0x0c, 0x08, 0x08, 0x08, 0x08, 0x08, 0x00, 0x00, 0x00], // 'i'
+    [0x10, 0x00, 0x00, 0x18, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x0e, 0x00], // 'j'
+    [0x02, 0x02, 0x02, 0x22, 0x12, 0x0a, 0x0e, 0x12, 0x22, 0x00, 0x00, 0x00], // 'k'
+    [0x0c, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x00, 0x00, 0x00], // 'l'
+    [0x00, 0x00, 0x00, 0x37, 0x49, 0x49, 0x49, 0x49, 0x49, 0x00, 0x00, 0x00], // 'm'
+    [0x00, 0x00, 0x00, 0x1e, 0x22, 0x22, 0x22, 0x22, 0x22, 0x00, 0x00, 0x00], // 'n'
+    [0x00, 0x00, 0x00, 0x1c, 0x22, 0x22, 0x22, 0x22, 0x1c, 0x00, 0x00, 0x00], // 'o'
+    [0x00, 0x00, 0x00, 0x1e, 0x22, 0x22, 0x22, 0x22, 0x1e, 0x02, 0x02, 0x02], // 'p'
+    [0x00, 0x00, 0x00, 0x3c, 0x22, 0x22, 0x22, 0x22, 0x3c, 0x20, 0x20, 0x20], // 'q'
+    [0x00, 0x00, 0x00, 0x1a, 0x26, 0x02, 0x02, 0x02, 0x02, 0x00, 0x00, 0x00], // 'r'
+    [0x00, 0x00, 0x00, 0x3c, 0x02, 0x0c, 0x10, 0x20, 0x1e, 0x00, 0x00, 0x00], // 's'
+    [0x00, 0x04, 0x04, 0x3c, 0x04, 0x04, 0x04, 0x04, 0x38, 0x00, 0x00, 0x00], // 't'
+    [0x00, 0x00, 0x00, 0x22, 0x22, 0x22, 0x22, 0x22, 0x3c, 0x00, 0x00, 0x00], // 'u'
+    [0x00, 0x00, 0x00, 0x22, 0x22, 0x14, 0x14, 0x08, 0x08, 0x00, 0x00, 0x00], // 'v'
+    [0x00, 0x00, 0x00, 0x41, 0x49, 0x49, 0x55, 0x36, 0x22, 0x00, 0x00, 0x00], // 'w'
+    [0x00, 0x00, 0x00, 0x22, 0x14, 0x08, 0x08, 0x14, 0x22, 0x00, 0x00, 0x00], // 'x'
+    [0x00, 0x00, 0x00, 0x22, 0x22, 0x22, 0x22, 0x22, 0x3c, 0x20, 0x20, 0x1c], // 'y'
+    [0x00, 0x00, 0x00, 0x3e, 0x20, 0x10, 0x08, 0x04, 0x3e, 0x00, 0x00, 0x00], // 'z'
+    [0x30, 0x08, 0x08, 0x08, 0x08, 0x06, 0x08, 0x08, 0x08, 0x08, 0x30, 0x00], // '{'
+    [0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x00], // '|'
+    [0x06, 0x08, 0x08, 0x08, 0x08, 0x30, 0x08, 0x08, 0x08, 0x08, 0x06, 0x00], // '}'
+    [0x00, 0x00, 0x00, 0x00, 0x4e, 0x39, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two 2x2 glyphs for `a` and `b` side by side: `a` has only its top
+    /// left pixel set, `b` is solid.
+    fn atlas_font() -> BitmapFont {
+        let clear = [0u8; 4];
+        let white = [255u8; 4];
+        let rows = [[white, clear, white, white], [clear, clear, white, white]];
+        let pixels = rows.iter().flatten().flatten().copied().collect();
+        BitmapFont::from_atlas(pixels, 4, 2, 2, 2, 'a'.into(), 2).unwrap()
+    }
+
+    fn lit(pixels: &[u8], width: usize) -> Vec<(usize, usize)> {
+        (0..pixels.len() / 4)
+            .filter(|i| pixels[i * 4 + 3] != 0)
+            .map(|i| (i % width, i / width))
+            .collect()
+    }
+
+    fn draw(font: &BitmapFont, text: &str, x: i32, y: i32, scale: u32) -> Vec<(usize, usize)> {
+        let mut pixels = vec![0; 8 * 8 * 4];
+        let mut surface = Surface {
+            pixels: &mut pixels,
+            width: 8,
+            height: 8,
+        };
+        font.draw(&mut surface, text, x, y, 0xffff_ffff, scale, false);
+        lit(&pixels, 8)
+    }
+
+    #[test]
+    fn measure_counts_the_longest_line() {
+        let font = BitmapFont::builtin();
+        assert_eq!(font.measure("ab\ncde", 2), (42, 48));
+        assert_eq!(font.measure("", 1), (0, 12));
+        assert_eq!(font.measure("héllo", 1), (35, 12));
+        assert_eq!(font.measure("a", u32::MAX), (u32::MAX, u32::MAX));
+    }
+
+    #[test]
+    fn draws_glyphs_from_an_atlas() {
+        let font = atlas_font();
+        assert_eq!(draw(&font, "a", 1, 1, 1), [(1, 1)]);
+        assert_eq!(draw(&font, "b", 0, 0, 1), [(0, 0), (1, 0), (0, 1), (1, 1)]);
+        assert_eq!(draw(&font, "ab\na", 0, 0, 1).len(), 6);
+        // characters without a glyph and no `?` to fall back on are skipped
+        assert_eq!(draw(&font, "zz", 0, 0, 1), []);
+    }
+
+    #[test]
+    fn scale_grows_every_pixel_into_a_block() {
+        let font = atlas_font();
+        assert_eq!(
+            draw(&font, "a", 2, 0, 3),
+            [
+                (2, 0),
+                (3, 0),
+                (4, 0),
+                (2, 1),
+                (3, 1),
+                (4, 1),
+                (2, 2),
+                (3, 2),
+                (4, 2)
+            ]
+        );
+        assert_eq!(draw(&font, "a", 0, 0, 0), []);
+    }
+
+    #[test]
+    fn huge_scales_and_positions_stay_in_range() {
+        let font = atlas_font();
+        assert_eq!(draw(&font, "aa\naa", 0, 0, 1 << 30).len(), 64);
+        assert_eq!(draw(&font, "bb", i32::MAX, i32::MAX, u32::MAX), []);
+        assert_eq!(draw(&font, "bb", i32::MIN, i32::MIN, u32::MAX).len(), 64);
+    }
+
+    #[test]
+    fn builtin_glyphs_match_the_bitmaps() {
+        let font = BitmapFont::builtin();
+        let mut pixels = vec![0; 7 * 12 * 4];
+        let mut surface = Surface {
+            pixels: &mut pixels,
+            width: 7,
+            height: 12,
+        };
+        font.draw(&mut surface, "!", 0, 0, 0xffff_ffff, 1, false);
+        let expected: Vec<_> = (0..12)
+            .flat_map(|y| (0..7).map(move |x| (x, y)))
+            .filter(|&(x, y)| BUILTIN_GLYPHS[1][y] & (1 << x) != 0)
+            .collect();
+        assert_eq!(lit(&pixels, 7), expected);
+
+        // unknown characters fall back to `?`
+        let mut fallback = vec![0; 7 * 12 * 4];
+        let mut question = vec![0; 7 * 12 * 4];
+        for (text, pixels) in [("\u{263a}", &mut fallback), ("?", &mut question)] {
+            let mut surface = Surface {
+                pixels,
+                width: 7,
+                height: 12,
+            };
+            font.draw(&mut surface, text, 0, 0, 0xffff_ffff, 1, false);
+        }
+        assert_eq!(fallback, question);
+    }
+
+    #[test]
+    fn atlas_must_fit_its_glyphs() {
+        let atlas = || vec![0; 4 * 2 * 4];
+        assert!(BitmapFont::from_atlas(atlas(), 4, 2, 0, 2, 0, 1).is_err());
+        assert!(BitmapFont::from_atlas(atlas(), 4, 2, 5, 2, 0, 1).is_err());
+        assert!(BitmapFont::from_atlas(atlas(), 4, 2, 2, 3, 0, 1).is_err());
+        assert!(BitmapFont::from_atlas(atlas(), 4, 2, 2, 2, 0, 3).is_err());
+        assert!(BitmapFont::from_atlas(atlas(), 4, 2, 2, 2, 0, 2).is_ok());
+        assert!(BitmapFont::from_atlas(atlas(), 4, 2, 1, 1, 0, 8).is_ok());
+    }
+}
@@ -0,0 +1,312 @@
+//! Virtual gamepads behind the `input` gamepad functions.
+//!
+//! Up to [`MAX_GAMEPADS`] pads are rebuilt every frame from a list of
+//! [`GamepadSource`]s, so the cartridge sees the same API whatever drives
+//! them. Buttons and axes are numbered like GLFW's gamepad mapping, which
+//! is what LWJGL exposes.
+
+use std::collections::VecDeque;
+
+pub const MAX_GAMEPADS: usize = 4;
+
+pub const BUTTON_A: u8 = 0;
+pub const BUTTON_B: u8 = 1;
+pub const BUTTON_X: u8 = 2;
+pub const BUTTON_Y: u8 = 3;
+pub const BUTTON_LEFT_BUMPER: u8 = 4;
+pub const BUTTON_RIGHT_BUMPER: u8 = 5;
+pub const BUTTON_BACK: u8 = 6;
+pub const BUTTON_START: u8 = 7;
+pub const BUTTON_GUIDE: u8 = 8;
+pub const BUTTON_LEFT_THUMB: u8 = 9;
+pub const BUTTON_RIGHT_THUMB: u8 = 10;
+pub const BUTTON_DPAD_UP: u8 = 11;
+pub const BUTTON_DPAD_RIGHT: u8 = 12;
+pub const BUTTON_DPAD_DOWN: u8 = 13;
+pub const BUTTON_DPAD_LEFT: u8 = 14;
+const BUTTON_COUNT: u8 = 15;
+
+pub const AXIS_LEFT_X: u8 = 0;
+/// Positive is down, as in GLFW.
+pub const AXIS_LEFT_Y: u8 = 1;
+pub const AXIS_RIGHT_X: u8 = 2;
+pub const AXIS_RIGHT_Y: u8 = 3;
+const AXIS_COUNT: usize = 4;
+
+pub const TRIGGER_LEFT: u8 = 0;
+pub const TRIGGER_RIGHT: u8 = 1;
+const TRIGGER_COUNT: usize = 2;
+
+/// Connection events kept for a cartridge that isn't polling.
+const MAX_QUEUED_EVENTS: usize = 64;
+/// Bytes `input::poll_gamepad_event` writes.
+pub const GAMEPAD_EVENT_SIZE: usize = 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PadState {
+    pub connected: bool,
+    /// Bit `n` is set while button `n` is down.
+    pub buttons: u16,
+    /// Sticks, -1 to 1.
+    pub axes: [f32; AXIS_COUNT],
+    /// 0 when released, 1 when fully pulled.
+    pub triggers: [f32; TRIGGER_COUNT],
+}
+
+impl PadState {
+    pub const fn press(&mut self, button: u8) {
+        if button < BUTTON_COUNT {
+            self.buttons |= 1 << button;
+        }
+    }
+
+    pub const fn is_button_down(&self, button: i32) -> bool {
+        button >= 0 && button < BUTTON_COUNT as i32 && self.buttons & (1 << button) != 0
+    }
+
+    pub fn axis(&self, axis: i32) -> f32 {
+        usize::try_from(axis)
+            .ok()
+            .and_then(|axis| self.axes.get(axis))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    pub fn trigger(&self, trigger: i32) -> f32 {
+        usize::try_from(trigger)
+            .ok()
+            .and_then(|trigger| self.triggers.get(trigger))
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum GamepadEventKind {
+    Connected = 0,
+    Disconnected = 1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GamepadEvent {
+    pub kind: GamepadEventKind,
+    pub pad: u32,
+}
+
+impl GamepadEvent {
+    /// Layout written to guest memory: kind and pad as little endian
+    /// 32-bit integers.
+    pub fn to_bytes(self) -> [u8; GAMEPAD_EVENT_SIZE] {
+        let mut bytes = [0; GAMEPAD_EVENT_SIZE];
+        bytes[0..4].copy_from_slice(&(self.kind as u32).to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.pad.to_le_bytes());
+        bytes
+    }
+}
+
+/// Something that drives virtual pads, like the keyboard or a physical
+/// controller backend.
+pub trait GamepadSource: Send {
+    /// Adds this frame's input to `pads`, which start out disconnected and
+    /// at rest. Sources mark the pads they drive as connected.
+    /// `is_key_down` reads the keyboard by LWJGL key code.
+    fn read(&mut self, is_key_down: &dyn Fn(i32) -> bool, pads: &mut [PadState; MAX_GAMEPADS]);
+}
+
+/// The pads the cartridge sees, with connection events and button edges.
+#[derive(Default)]
+pub struct Gamepads {
+    sources: Vec<Box<dyn GamepadSource>>,
+    pads: [PadState; MAX_GAMEPADS],
+    previous_buttons: [u16; MAX_GAMEPADS],
+    events: VecDeque<GamepadEvent>,
+}
+
+impl Gamepads {
+    pub fn add_source(&mut self, source: Box<dyn GamepadSource>) {
+        self.sources.push(source);
+    }
+
+    /// Rebuilds the pads from the sources once per frame.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn poll(&mut self, is_key_down: &dyn Fn(i32) -> bool) {
+        let mut pads = [PadState::default(); MAX_GAMEPADS];
+        for source in &mut self.sources {
+            source.read(is_key_down, &mut pads);
+        }
+
+        for (index, (old, new)) in self.pads.iter().zip(&mut pads).enumerate() {
+            if !new.connected {
+                *new = PadState::default();
+            }
+            if old.connected != new.connected {
+                let kind = if new.connected {
+                    GamepadEventKind::Connected
+                } else {
+                    GamepadEventKind::Disconnected
+                };
+                if self.events.len() == MAX_QUEUED_EVENTS {
+                    self.events.pop_front();
+                }
+                self.events.push_back(GamepadEvent {
+                    kind,
+                    pad: index as u32,
+                });
+            }
+            self.previous_buttons[index] = old.buttons;
+        }
+        self.pads = pads;
+    }
+
+    /// The pad's state, at rest and disconnected for unknown pads.
+    pub fn pad(&self, pad: i32) -> PadState {
+        usize::try_from(pad)
+            .ok()
+            .and_then(|pad| self.pads.get(pad))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn pressed_this_frame(&self, pad: i32, button: i32) -> bool {
+        let Some(&previous) = usize::try_from(pad)
+            .ok()
+            .and_then(|pad| self.previous_buttons.get(pad))
+        else {
+            return false;
+        };
+        self.pad(pad).is_button_down(button) && previous & (1 << button) == 0
+    }
+
+    pub fn poll_event(&mut self) -> Option<GamepadEvent> {
+        self.events.pop_front()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PadInput {
+    Button(u8),
+    /// Pushes the axis towards -1 or 1.
+    Axis(u8, f32),
+    Trigger(u8),
+}
+
+/// LWJGL key codes and what they do on the keyboard pad.
+const DEFAULT_BINDINGS: &[(i32, PadInput)] = &[
+    (265, PadInput::Button(BUTTON_DPAD_UP)),     // up
+    (262, PadInput::Button(BUTTON_DPAD_RIGHT)),  // right
+    (264, PadInput::Button(BUTTON_DPAD_DOWN)),   // down
+    (263, PadInput::Button(BUTTON_DPAD_LEFT)),   // left
+    (87, PadInput::Axis(AXIS_LEFT_Y, -1.0)),     // W
+    (65, PadInput::Axis(AXIS_LEFT_X, -1.0)),     // A
+    (83, PadInput::Axis(AXIS_LEFT_Y, 1.0)),      // S
+    (68, PadInput::Axis(AXIS_LEFT_X, 1.0)),      // D
+    (73, PadInput::Axis(AXIS_RIGHT_Y, -1.0)),    // I
+    (74, PadInput::Axis(AXIS_RIGHT_X, -1.0)),    // J
+    (75, PadInput::Axis(AXIS_RIGHT_Y, 1.0)),     // K
+    (76, PadInput::Axis(AXIS_RIGHT_X, 1.0)),     // L
+    (90, PadInput::Button(BUTTON_A)),            // Z
+    (88, PadInput::Button(BUTTON_B)),            // X
+    (67, PadInput::Button(BUTTON_X)),            // C
+    (86, PadInput::Button(BUTTON_Y)),            // V
+    (81, PadInput::Button(BUTTON_LEFT_BUMPER)),  // Q
+    (69, PadInput::Button(BUTTON_RIGHT_BUMPER)), // E
+    (49, PadInput::Trigger(TRIGGER_LEFT)),       // 1
+    (51, PadInput::Trigger(TRIGGER_RIGHT)),      // 3
+    (340, PadInput::Button(BUTTON_LEFT_THUMB)),  // left shift
+    (344, PadInput::Button(BUTTON_RIGHT_THUMB)), // right shift
+    (257, PadInput::Button(BUTTON_START)),       // enter
+    (259, PadInput::Button(BUTTON_BACK)),        // backspace
+    (71, PadInput::Button(BUTTON_GUIDE)),        // G
+];
+
+/// A pad driven by the keyboard, for playing and testing without a
+/// controller.
+pub struct KeyboardPad {
+    pad: usize,
+    bindings: Vec<(i32, PadInput)>,
+}
+
+impl KeyboardPad {
+    pub fn new(pad: usize) -> Self {
+        Self {
+            pad,
+            bindings: DEFAULT_BINDINGS.to_vec(),
+        }
+    }
+}
+
+impl GamepadSource for KeyboardPad {
+    fn read(&mut self, is_key_down: &dyn Fn(i32) -> bool, pads: &mut [PadState; MAX_GAMEPADS]) {
+        let Some(pad) = pads.get_mut(self.pad) else {
+            return;
+        };
+        pad.connected = true;
+        for &(key, input) in &self.bindings {
+            if !is_key_down(key) {
+                continue;
+            }
+            match input {
+                PadInput::Button(button) => pad.press(button),
+                PadInput::Axis(axis, direction) => {
+                    let value = &mut pad.axes[usize::from(axis)];
+                    *value = (*value + direction).clamp(-1.0, 1.0);
+                }
+                PadInput::Trigger(trigger) => pad.triggers[usize::from(trigger)] = 1.0,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::*;
+
+    fn keyboard_pads() -> Gamepads {
+        let mut gamepads = Gamepads::default();
+        gamepads.add_source(Box::new(KeyboardPad::new(1)));
+        gamepads
+    }
+
+    #[test]
+    fn keyboard_drives_its_pad() {
+        let mut gamepads = keyboard_pads();
+        gamepads.poll(&|key| matches!(key, 90 | 87 | 68 | 51));
+
+        let pad = gamepads.pad(1);
+        assert!(pad.connected);
+        assert!(pad.is_button_down(i32::from(BUTTON_A)));
+        assert!(!pad.is_button_down(i32::from(BUTTON_B)));
+        assert_eq!(pad.axes, [1.0, -1.0, 0.0, 0.0]);
+        assert_eq!(pad.triggers, [0.0, 1.0]);
+        assert!(!gamepads.pad(0).connected);
+        assert!(!gamepads.pad(7).connected);
+    }
+
+    #[test]
+    fn opposite_keys_cancel() {
+        let mut gamepads = keyboard_pads();
+        gamepads.poll(&|key| matches!(key, 65 | 68));
+        assert_eq!(gamepads.pad(1).axes, [0.0; 4]);
+    }
+
+    #[test]
+    fn connection_events_and_edges() {
+        let mut gamepads = keyboard_pads();
+        gamepads.poll(&|key| key == 90);
+        assert_eq!(
+            gamepads.poll_event(),
+            Some(GamepadEvent {
+                kind: GamepadEventKind::Connected,
+                pad: 1
+            })
+        );
+        assert_eq!(gamepads.poll_event(), None);
+        assert!(gamepads.pressed_this_frame(1, i32::from(BUTTON_A)));
+
+        gamepads.poll(&|key| key == 90);
+        assert!(!gamepads.pressed_this_frame(1, i32::from(BUTTON_A)));
+        assert_eq!(gamepads.poll_event(), None);
+    }
+}
@@ -47,6 +47,17 @@ impl MeshRegistry {
     pub fn find_mesh(&self, id: MeshId) -> Option<FastCell<GpuMesh>> {
         self.meshes.get(&id).map(|f| f.value().clone())
     }
+
+    /// Every registered mesh, by id.
+    pub fn entries(&self) -> Vec<(MeshId, FastCell<GpuMesh>)> {
+        let mut entries: Vec<_> = self
+            .meshes
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        entries.sort_by_key(|(id, _)| *id);
+        entries
+    }
 }
 
 pub fn get_mesh_registry() -> &'static Mutex<MeshRegistry> {
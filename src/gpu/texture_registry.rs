@@ -38,6 +38,17 @@ impl TextureRegistry {
         self.textures.get(&id).map(|f| f.value().clone())
     }
 
+    /// Every registered texture, by id.
+    pub fn entries(&self) -> Vec<(TextureId, FastCell<Texture2D>)> {
+        let mut entries: Vec<_> = self
+            .textures
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        entries.sort_by_key(|(id, _)| *id);
+        entries
+    }
+
     pub fn get_default_texture(&self) -> FastCell<Texture2D> {
         self.missing_texture.clone()
     }
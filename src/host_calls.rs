@@ -0,0 +1,167 @@
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+use wasmtime::{Caller, Func, Val};
+
+use crate::{
+    cli::CliArgs,
+    profiler::{begin_host_call_profiler, end_profiler},
+    wasm::{WASMHostState, WASMRuntime},
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HostCallStats {
+    pub count: u64,
+    pub total: Duration,
+}
+
+/// Per-function host call counts and time, collected frame by frame.
+#[derive(Default)]
+pub struct HostCallTracer {
+    current: HashMap<String, HostCallStats>,
+    last_frame: HashMap<String, HostCallStats>,
+    frame_index: u64,
+    log: Option<BufWriter<File>>,
+}
+
+impl HostCallTracer {
+    pub fn open_log(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.log = Some(BufWriter::new(File::create(path)?));
+        log::info!("streaming host calls to {}", path.display());
+        Ok(())
+    }
+
+    pub fn record(&mut self, label: &str, params: &[Val], results: &[Val], elapsed: Duration) {
+        let stats = self.current.entry(label.to_string()).or_default();
+        stats.count += 1;
+        stats.total += elapsed;
+
+        if let Some(log) = &mut self.log {
+            let line = format!(
+                "frame={} {label}({}) -> ({}) {:.3}us",
+                self.frame_index,
+                format_vals(params),
+                format_vals(results),
+                elapsed.as_secs_f64() * 1_000_000.0,
+            );
+            if let Err(e) = writeln!(log, "{line}") {
+                log::error!("failed to write host call log, disabling it: {e}");
+                self.log = None;
+            }
+        }
+    }
+
+    pub fn next_frame(&mut self) {
+        self.last_frame = std::mem::take(&mut self.current);
+        self.frame_index += 1;
+
+        if let Some(log) = &mut self.log
+            && let Err(e) = log.flush()
+        {
+            log::error!("failed to flush host call log, disabling it: {e}");
+            self.log = None;
+        }
+    }
+
+    /// Calls made during the last completed frame, most expensive first.
+    pub fn last_frame_stats(&self) -> Vec<(String, HostCallStats)> {
+        let mut stats: Vec<_> = self
+            .last_frame
+            .iter()
+            .map(|(label, stats)| (label.clone(), *stats))
+            .collect();
+        stats.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total));
+        stats
+    }
+}
+
+pub fn format_vals(vals: &[Val]) -> String {
+    vals.iter()
+        .map(|val| match val {
+            Val::I32(v) => v.to_string(),
+            Val::I64(v) => v.to_string(),
+            Val::F32(bits) => f32::from_bits(*bits).to_string(),
+            Val::F64(bits) => f64::from_bits(*bits).to_string(),
+            other => format!("{other:?}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn get_host_call_tracer() -> &'static Mutex<HostCallTracer> {
+    static HOST_CALL_TRACER: OnceLock<Mutex<HostCallTracer>> = OnceLock::new();
+    HOST_CALL_TRACER.get_or_init(|| Mutex::new(HostCallTracer::default()))
+}
+
+pub fn next_host_call_frame() {
+    get_host_call_tracer().lock().next_frame();
+}
+
+pub const fn wants_instrumentation(args: &CliArgs) -> bool {
+    args.trace_host_calls || args.host_call_stats || args.host_call_log.is_some()
+}
+
+/// Re-registers every host function in the linker behind a wrapper that
+/// records the call in the [`HostCallTracer`] and, with `--trace-host-calls`,
+/// opens a profiler scope named `module::name` for the duration of the call.
+///
+/// Must run after all modules are linked and before the cartridge is
+/// instantiated. The wrappers go through `Func::call`, so this is opt-in.
+/// The `profiler` module is left alone, since its functions open and close
+/// scopes themselves.
+pub fn instrument_host_calls(runtime: &mut WASMRuntime, args: &CliArgs) -> anyhow::Result<()> {
+    if let Some(path) = &args.host_call_log {
+        get_host_call_tracer().lock().open_log(path)?;
+    }
+
+    let linker = runtime.linker.get_mut();
+    let store = runtime.store.get_mut();
+
+    let funcs: Vec<(String, String, Func)> = linker
+        .iter(&mut *store)
+        .filter(|(module, _, _)| *module != "profiler")
+        .filter_map(|(module, name, item)| {
+            item.into_func()
+                .map(|func| (module.to_string(), name.to_string(), func))
+        })
+        .collect();
+
+    let profile_scopes = args.trace_host_calls;
+    linker.allow_shadowing(true);
+    for (module, name, func) in funcs {
+        let ty = func.ty(&*store);
+        let label = format!("{module}::{name}");
+
+        linker.func_new(
+            &module,
+            &name,
+            ty,
+            move |mut caller: Caller<'_, WASMHostState>, params: &[Val], results: &mut [Val]| {
+                if profile_scopes {
+                    begin_host_call_profiler(&label);
+                }
+                let start = Instant::now();
+                let result = func.call(&mut caller, params, results);
+                let elapsed = start.elapsed();
+                if profile_scopes {
+                    end_profiler();
+                }
+
+                get_host_call_tracer()
+                    .lock()
+                    .record(&label, params, results, elapsed);
+                result
+            },
+        )?;
+    }
+    linker.allow_shadowing(false);
+
+    log::info!("host calls instrumented");
+    Ok(())
+}
@@ -0,0 +1,333 @@
+//! Where the `input` host functions get their input from.
+//!
+//! Once per frame the host asks its [`InputSource`] for the frame's
+//! [`InputEvent`]s and applies them to the [`InputState`] the host functions
+//! read. The same cartridge can then be driven by the window, the terminal,
+//! a recorded movie or a script, and none of the host functions touch
+//! macroquad's input directly.
+
+mod movie;
+mod script;
+
+use std::{collections::VecDeque, fmt::Display, str::FromStr};
+
+use crate::{
+    cartridge::Resolution,
+    cli::CliArgs,
+    keyboard::{KeyEvent, KeyEventKind, KeyboardEvents},
+    mouse::MouseEvents,
+    terminal::{QuitWatcher, TerminalInput},
+    window_input::WindowSource,
+};
+
+pub use movie::{MovieRecorder, MovieSource};
+pub use script::ScriptedSource;
+
+/// Characters kept for a cartridge that isn't reading them.
+const MAX_QUEUED_CHARS: usize = 256;
+
+pub trait InputSource: Send {
+    /// Adds the input for the next frame to `events`.
+    fn poll(&mut self, events: &mut Vec<InputEvent>);
+
+    /// Whether the session should end, e.g. after Ctrl+C in the terminal or
+    /// at the end of a movie.
+    fn finished(&self) -> bool {
+        false
+    }
+
+    /// Called when the cursor is grabbed or released. Sources that work out
+    /// relative motion from cursor positions start over from the next one.
+    fn reset_motion(&mut self) {}
+
+    /// Clock readings recorded for the frame just polled, which the
+    /// cartridge gets instead of the real time. Only movies have these.
+    fn clock_readings(&mut self) -> Option<Vec<i64>> {
+        None
+    }
+}
+
+/// No input at all.
+pub struct NullSource;
+
+impl InputSource for NullSource {
+    fn poll(&mut self, _events: &mut Vec<InputEvent>) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    Key(KeyEvent),
+    /// Typed text, for `get_key_code`.
+    Char(char),
+    /// Cursor position in framebuffer pixels, and whether it is over the
+    /// window.
+    MouseMove {
+        x: f32,
+        y: f32,
+        inside: bool,
+    },
+    /// Relative motion for `get_mouse_accumulated_dx`/`dy`, in device
+    /// units, positive right and down.
+    MouseDelta {
+        dx: f64,
+        dy: f64,
+    },
+    /// LWJGL button id.
+    MouseButton {
+        button: i32,
+        down: bool,
+    },
+    /// In notches, positive is right and up.
+    Wheel {
+        x: f64,
+        y: f64,
+    },
+}
+
+/// The text form used by movies and scripts, e.g. `key press 65 1` or
+/// `move 10 20.5 in`.
+impl Display for InputEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Key(event) => {
+                let kind = match event.kind {
+                    KeyEventKind::Press => "press",
+                    KeyEventKind::Release => "release",
+                    KeyEventKind::Repeat => "repeat",
+                };
+                write!(f, "key {kind} {} {}", event.key, event.mods)
+            }
+            Self::Char(ch) => write!(f, "char {}", u32::from(*ch)),
+            Self::MouseMove { x, y, inside } => {
+                write!(f, "move {x} {y} {}", if *inside { "in" } else { "out" })
+            }
+            Self::MouseDelta { dx, dy } => write!(f, "delta {dx} {dy}"),
+            Self::MouseButton { button, down } => {
+                write!(f, "button {button} {}", if *down { "down" } else { "up" })
+            }
+            Self::Wheel { x, y } => write!(f, "wheel {x} {y}"),
+        }
+    }
+}
+
+impl FromStr for InputEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let number = |index: usize| -> anyhow::Result<f64> {
+            let word = words
+                .get(index)
+                .ok_or_else(|| anyhow::anyhow!("`{s}` is missing a value"))?;
+            word.parse()
+                .map_err(|_| anyhow::anyhow!("`{word}` is not a number"))
+        };
+        let word = |index: usize| words.get(index).copied().unwrap_or("");
+
+        #[allow(clippy::cast_possible_truncation)]
+        let event = match word(0) {
+            "key" => {
+                let kind = match word(1) {
+                    "press" => KeyEventKind::Press,
+                    "release" => KeyEventKind::Release,
+                    "repeat" => KeyEventKind::Repeat,
+                    other => anyhow::bail!("unknown key event `{other}`"),
+                };
+                let key = word(2).parse()?;
+                let mods = if words.len() > 3 { word(3).parse()? } else { 0 };
+                Self::Key(KeyEvent::from_lwjgl(kind, key, mods))
+            }
+            "char" => {
+                let code = word(1).parse()?;
+                Self::Char(
+                    char::from_u32(code)
+                        .ok_or_else(|| anyhow::anyhow!("{code} is not a character"))?,
+                )
+            }
+            "move" => Self::MouseMove {
+                x: number(1)? as f32,
+                y: number(2)? as f32,
+                inside: word(3) != "out",
+            },
+            "delta" => Self::MouseDelta {
+                dx: number(1)?,
+                dy: number(2)?,
+            },
+            "button" => Self::MouseButton {
+                button: word(1).parse()?,
+                down: match word(2) {
+                    "down" => true,
+                    "up" => false,
+                    other => anyhow::bail!("expected `down` or `up`, got `{other}`"),
+                },
+            },
+            "wheel" => Self::Wheel {
+                x: number(1)?,
+                y: number(2)?,
+            },
+            other => anyhow::bail!("unknown input event `{other}`"),
+        };
+        Ok(event)
+    }
+}
+
+/// What the `input` host functions read.
+#[derive(Default)]
+pub struct InputState {
+    pub keyboard: KeyboardEvents,
+    pub mouse: MouseEvents,
+    chars: VecDeque<char>,
+}
+
+impl InputState {
+    /// Starts a new frame for per-frame edges and deltas. Queued events and
+    /// characters stay until the cartridge reads them.
+    pub fn begin_frame(&mut self) {
+        self.keyboard.begin_frame();
+        self.mouse.begin_frame();
+    }
+
+    pub fn apply(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Key(event) => self.keyboard.push(event),
+            InputEvent::Char(ch) => {
+                if self.chars.len() == MAX_QUEUED_CHARS {
+                    self.chars.pop_front();
+                }
+                self.chars.push_back(ch);
+            }
+            InputEvent::MouseMove { x, y, inside } => self.mouse.move_to(x, y, inside),
+            InputEvent::MouseDelta { dx, dy } => self.mouse.add_delta(dx, dy),
+            InputEvent::MouseButton { button, down } => self.mouse.set_button(button, down),
+            InputEvent::Wheel { x, y } => self.mouse.scroll(x, y),
+        }
+    }
+
+    pub fn pop_char(&mut self) -> Option<char> {
+        self.chars.pop_front()
+    }
+}
+
+/// Events at the frames they happen on, handed out one frame at a time.
+struct Timeline {
+    events: Vec<(u64, InputEvent)>,
+    next: usize,
+    frame: u64,
+}
+
+impl Timeline {
+    fn new(mut events: Vec<(u64, InputEvent)>) -> Self {
+        // stable, so events on the same frame keep their order
+        events.sort_by_key(|&(frame, _)| frame);
+        Self {
+            events,
+            next: 0,
+            frame: 0,
+        }
+    }
+
+    fn poll(&mut self, out: &mut Vec<InputEvent>) {
+        while let Some(&(frame, event)) = self.events.get(self.next)
+            && frame <= self.frame
+        {
+            out.push(event);
+            self.next += 1;
+        }
+        self.frame += 1;
+    }
+
+    /// Frames polled so far.
+    const fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    const fn is_done(&self) -> bool {
+        self.next == self.events.len()
+    }
+}
+
+/// The input source `args` ask for: a movie or script if given, otherwise
+/// the terminal or the window.
+pub fn open_input_source(
+    args: &CliArgs,
+    resolution: Resolution,
+) -> anyhow::Result<Box<dyn InputSource>> {
+    let replayed: Option<Box<dyn InputSource>> = if let Some(path) = &args.play_input {
+        Some(Box::new(MovieSource::open(path)?))
+    } else if let Some(path) = &args.input_script {
+        Some(Box::new(ScriptedSource::open(path)?))
+    } else {
+        None
+    };
+
+    Ok(match (replayed, args.terminal) {
+        // the terminal is in raw mode, so something still has to watch for
+        // Ctrl+C
+        (Some(source), true) => Box::new(QuitWatcher::new(source)),
+        (Some(source), false) => source,
+        (None, true) => Box::new(TerminalInput::default()),
+        (None, false) => Box::new(WindowSource::new(resolution)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_round_trip_through_text() {
+        let events = [
+            InputEvent::Key(KeyEvent::from_lwjgl(KeyEventKind::Repeat, 65, 3)),
+            InputEvent::Char('é'),
+            InputEvent::MouseMove {
+                x: 10.5,
+                y: 3.0,
+                inside: false,
+            },
+            InputEvent::MouseDelta { dx: -2.0, dy: 0.25 },
+            InputEvent::MouseButton {
+                button: 4,
+                down: true,
+            },
+            InputEvent::Wheel { x: 0.0, y: -1.0 },
+        ];
+        for event in events {
+            assert_eq!(event.to_string().parse::<InputEvent>().unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn state_follows_events() {
+        let mut state = InputState::default();
+        state.apply("key press 65".parse().unwrap());
+        state.apply("char 97".parse().unwrap());
+        state.apply("wheel 0 1".parse().unwrap());
+        // the window can't report these, but movies and scripts can
+        state.apply("button 5 down".parse().unwrap());
+        state.apply("move 3 4 out".parse().unwrap());
+        assert!(state.mouse.is_button_down(5));
+        assert!(!state.mouse.is_inside());
+        assert!(state.keyboard.is_key_down(65));
+        assert_eq!(state.pop_char(), Some('a'));
+        assert_eq!(state.mouse.wheel(), (0.0, 1.0));
+
+        state.begin_frame();
+        state.apply("key release 65".parse().unwrap());
+        assert!(!state.keyboard.is_key_down(65));
+        assert_eq!(state.mouse.wheel(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn timeline_hands_out_events_by_frame() {
+        let click = |down| InputEvent::MouseButton { button: 0, down };
+        let mut timeline = Timeline::new(vec![(2, click(false)), (0, click(true))]);
+        let mut frames = Vec::new();
+        for _ in 0..3 {
+            let mut events = Vec::new();
+            timeline.poll(&mut events);
+            frames.push(events);
+        }
+        assert_eq!(frames, [vec![click(true)], vec![], vec![click(false)]]);
+        assert!(timeline.is_done());
+    }
+}
@@ -0,0 +1,240 @@
+//! Input movies: every input event of a session with the frame it arrived
+//! on, and every clock reading the cartridge made, so a session can be
+//! played back exactly.
+//!
+//! A movie is a text file starting with [`HEADER`], followed by one
+//! `<frame> <event>` line per event in the form [`InputEvent`] displays as,
+//! `<frame> time <nanos>` lines with the frame's clock readings in order,
+//! and an `<frame> end` line with the number of frames recorded.
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use crate::input_source::{InputEvent, InputSource, Timeline};
+
+const HEADER: &str = "gooseboy-movie 1";
+
+/// Plays a movie back, finishing after its last recorded frame.
+pub struct MovieSource {
+    timeline: Timeline,
+    /// From the `end` line, for movies that have one.
+    end: Option<u64>,
+    /// Clock readings by frame, in order.
+    readings: Vec<(u64, i64)>,
+    next_reading: usize,
+}
+
+impl MovieSource {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("failed to load movie {}", path.display()))
+    }
+
+    fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line.trim()) != Some(HEADER) {
+            anyhow::bail!("not an input movie, expected `{HEADER}` on the first line");
+        }
+
+        let mut events = Vec::new();
+        let mut readings = Vec::new();
+        let mut end = None;
+        for (number, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (frame, event) = line.split_once(' ').unwrap_or((line, ""));
+            let parsed = frame
+                .parse::<u64>()
+                .map_err(anyhow::Error::from)
+                .and_then(|frame| {
+                    if event == "end" {
+                        end = Some(frame);
+                    } else if let Some(nanos) = event.strip_prefix("time ") {
+                        readings.push((frame, nanos.trim().parse()?));
+                    } else {
+                        events.push((frame, event.parse()?));
+                    }
+                    Ok(())
+                });
+            parsed.with_context(|| format!("line {}", number + 1))?;
+        }
+
+        // stable, so readings on the same frame keep their order
+        readings.sort_by_key(|&(frame, _)| frame);
+        Ok(Self {
+            timeline: Timeline::new(events),
+            end,
+            readings,
+            next_reading: 0,
+        })
+    }
+}
+
+impl InputSource for MovieSource {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) {
+        self.timeline.poll(events);
+    }
+
+    fn finished(&self) -> bool {
+        self.end.map_or_else(
+            || self.timeline.is_done(),
+            |end| self.timeline.frame() >= end,
+        )
+    }
+
+    fn clock_readings(&mut self) -> Option<Vec<i64>> {
+        // older movies have no readings, so they run on the real clock
+        if self.readings.is_empty() {
+            return None;
+        }
+        let frame = self.timeline.frame().checked_sub(1)?;
+        let mut readings = Vec::new();
+        while let Some(&(at, nanos)) = self.readings.get(self.next_reading)
+            && at <= frame
+        {
+            if at == frame {
+                readings.push(nanos);
+            }
+            self.next_reading += 1;
+        }
+        Some(readings)
+    }
+}
+
+/// Writes the input the cartridge got to a movie, after host screens and
+/// key bindings had their say, so playing it back depends on neither. The
+/// `end` line is written when the recorder is dropped.
+pub struct MovieRecorder {
+    out: BufWriter<File>,
+    frame: u64,
+    /// Whether events were written since the last flush.
+    unflushed: bool,
+}
+
+impl MovieRecorder {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        writeln!(out, "{HEADER}")?;
+        Ok(Self {
+            out,
+            frame: 0,
+            unflushed: false,
+        })
+    }
+
+    pub fn record(&mut self, event: InputEvent) {
+        if let Err(e) = writeln!(self.out, "{} {event}", self.frame) {
+            log::error!("failed to record input: {e}");
+        }
+        self.unflushed = true;
+    }
+
+    /// Records the clock readings made since the last frame's input was
+    /// recorded. Readings from before the first frame aren't kept; a movie
+    /// plays those back on the real clock too.
+    pub fn record_clock(&mut self, readings: &[i64]) {
+        let Some(frame) = self.frame.checked_sub(1) else {
+            return;
+        };
+        for nanos in readings {
+            if let Err(e) = writeln!(self.out, "{frame} time {nanos}") {
+                log::error!("failed to record input: {e}");
+            }
+            self.unflushed = true;
+        }
+    }
+
+    /// Ends the current frame; later events are recorded on the next one.
+    pub fn next_frame(&mut self) {
+        // keep what was recorded if the emulator crashes
+        if std::mem::take(&mut self.unflushed)
+            && let Err(e) = self.out.flush()
+        {
+            log::error!("failed to record input: {e}");
+        }
+        self.frame += 1;
+    }
+}
+
+impl Drop for MovieRecorder {
+    fn drop(&mut self) {
+        let result = writeln!(self.out, "{} end", self.frame).and_then(|()| self.out.flush());
+        if let Err(e) = result {
+            log::error!("failed to finish the input movie: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_until_the_end_line() {
+        let mut movie =
+            MovieSource::parse("gooseboy-movie 1\n0 key press 65 0\n1 key release 65 0\n3 end\n")
+                .unwrap();
+        let mut polled = 0;
+        while !movie.finished() {
+            let mut events = Vec::new();
+            movie.poll(&mut events);
+            polled += 1;
+        }
+        assert_eq!(polled, 3);
+    }
+
+    #[test]
+    fn recordings_play_back_frame_by_frame() {
+        let path = std::env::temp_dir().join(format!("movie-{}.txt", std::process::id()));
+        let click = |down| InputEvent::MouseButton { button: 0, down };
+
+        let mut recorder = MovieRecorder::create(&path).unwrap();
+        recorder.record_clock(&[1]);
+        recorder.record(click(true));
+        recorder.next_frame();
+        recorder.record_clock(&[10, 11]);
+        recorder.next_frame();
+        recorder.record(click(false));
+        recorder.record("key release 65 0".parse().unwrap());
+        recorder.next_frame();
+        recorder.record_clock(&[30]);
+        drop(recorder);
+
+        let mut movie = MovieSource::open(&path).unwrap();
+        let mut frames = Vec::new();
+        let mut readings = Vec::new();
+        while !movie.finished() {
+            let mut events = Vec::new();
+            movie.poll(&mut events);
+            frames.push(events);
+            readings.push(movie.clock_readings().unwrap());
+        }
+        assert_eq!(readings, [vec![10, 11], vec![], vec![30]]);
+        assert_eq!(
+            frames,
+            [
+                vec![click(true)],
+                vec![],
+                vec![click(false), "key release 65 0".parse().unwrap()],
+            ]
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(MovieSource::parse("0 key press 65 0\n").is_err());
+        assert!(MovieSource::parse("gooseboy-movie 1\nx key press 65 0\n").is_err());
+    }
+}
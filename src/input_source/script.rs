@@ -0,0 +1,193 @@
+//! Hand-written input timelines, for driving a cartridge from a file.
+//!
+//! Each line is `<when> <command>`, where `<when>` is an absolute frame
+//! number or `+<frames>` after the previous line. Commands:
+//!
+//! - `press <key>`, `release <key>`, `tap <key>` (released a frame later)
+//! - `type <text>`, one character per frame
+//! - `move <x> <y>` in framebuffer pixels, `click [button]`
+//! - `quit`, which ends the session
+//! - any event in the movie format, e.g. `wheel 0 -1`
+//!
+//! Keys are named like macroquad's `KeyCode` (`A`, `Space`, `LeftShift`,
+//! `Key1`, case and `_` don't matter), given as a single character, or as
+//! an LWJGL key code. Blank lines and `#` comments are skipped.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+
+use crate::{
+    input_source::{InputEvent, InputSource, Timeline},
+    keyboard::{KeyEvent, KeyEventKind, MOD_SHIFT, char_key, parse_key},
+};
+
+/// The LWJGL key code for the left shift key.
+const KEY_LEFT_SHIFT: i32 = 340;
+
+pub struct ScriptedSource {
+    timeline: Timeline,
+    /// Frame of the `quit` command, if there is one.
+    quit: Option<u64>,
+}
+
+impl ScriptedSource {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&text)
+            .with_context(|| format!("failed to load input script {}", path.display()))
+    }
+
+    fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut events = Vec::new();
+        let mut quit = None;
+        let mut previous = 0;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = parse_line(line, previous, &mut events, &mut quit);
+            previous = parsed.with_context(|| format!("line {}", number + 1))?;
+        }
+
+        Ok(Self {
+            timeline: Timeline::new(events),
+            quit,
+        })
+    }
+}
+
+/// Adds one line's events, returning the frame it happens on.
+fn parse_line(
+    line: &str,
+    previous: u64,
+    events: &mut Vec<(u64, InputEvent)>,
+    quit: &mut Option<u64>,
+) -> anyhow::Result<u64> {
+    let (when, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let frame = match when.strip_prefix('+') {
+        Some(offset) => previous + offset.parse::<u64>()?,
+        None => when.parse()?,
+    };
+    let rest = rest.trim_start();
+    let (command, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let args = args.trim();
+
+    let key = |kind, key| InputEvent::Key(KeyEvent::from_lwjgl(kind, key, 0));
+    match command {
+        "press" => events.push((frame, key(KeyEventKind::Press, parse_key(args)?))),
+        "release" => events.push((frame, key(KeyEventKind::Release, parse_key(args)?))),
+        "tap" => {
+            let code = parse_key(args)?;
+            events.push((frame, key(KeyEventKind::Press, code)));
+            events.push((frame + 1, key(KeyEventKind::Release, code)));
+        }
+        "type" => {
+            for (offset, ch) in (0..).zip(args.chars()) {
+                type_char(events, frame + offset, ch);
+            }
+        }
+        "move" => {
+            let event = format!("move {args} in").parse()?;
+            events.push((frame, event));
+        }
+        "click" => {
+            let button = if args.is_empty() { 0 } else { args.parse()? };
+            events.push((frame, InputEvent::MouseButton { button, down: true }));
+            events.push((
+                frame + 1,
+                InputEvent::MouseButton {
+                    button,
+                    down: false,
+                },
+            ));
+        }
+        "quit" => *quit = Some(frame),
+        _ => events.push((frame, rest.parse()?)),
+    }
+    Ok(frame)
+}
+
+/// Presses the character's key, with shift for capitals, and releases it on
+/// the next frame.
+fn type_char(events: &mut Vec<(u64, InputEvent)>, frame: u64, ch: char) {
+    let code = char_key(ch);
+    let shift = ch.is_ascii_uppercase();
+    let mods = if shift { MOD_SHIFT } else { 0 };
+    let key = |kind, key, mods| InputEvent::Key(KeyEvent::from_lwjgl(kind, key, mods));
+
+    if shift {
+        events.push((frame, key(KeyEventKind::Press, KEY_LEFT_SHIFT, MOD_SHIFT)));
+    }
+    if let Some(code) = code {
+        events.push((frame, key(KeyEventKind::Press, code, mods)));
+    }
+    events.push((frame, InputEvent::Char(ch)));
+    if let Some(code) = code {
+        events.push((frame + 1, key(KeyEventKind::Release, code, mods)));
+    }
+    if shift {
+        events.push((frame + 1, key(KeyEventKind::Release, KEY_LEFT_SHIFT, 0)));
+    }
+}
+
+impl InputSource for ScriptedSource {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) {
+        self.timeline.poll(events);
+    }
+
+    fn finished(&self) -> bool {
+        self.quit.is_some_and(|quit| self.timeline.frame() > quit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(script: &str, count: usize) -> Vec<Vec<String>> {
+        let mut source = ScriptedSource::parse(script).unwrap();
+        (0..count)
+            .map(|_| {
+                let mut events = Vec::new();
+                source.poll(&mut events);
+                events.iter().map(ToString::to_string).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn relative_frames_and_taps() {
+        let frames = frames("# jump\n2 tap space\n+1 click 1\n", 5);
+        assert_eq!(
+            frames,
+            [
+                vec![],
+                vec![],
+                vec!["key press 32 0".to_string()],
+                vec!["key release 32 0".to_string(), "button 1 down".to_string()],
+                vec!["button 1 up".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn typing_and_quit() {
+        let mut source = ScriptedSource::parse("0 type Hi\n1 quit\n").unwrap();
+        let mut events = Vec::new();
+        source.poll(&mut events);
+        assert!(events.contains(&InputEvent::Char('H')));
+        assert!(!source.finished());
+        source.poll(&mut events);
+        assert!(events.contains(&InputEvent::Char('i')));
+        assert!(source.finished());
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        let error = ScriptedSource::parse("0 press a\n3 hop\n").err().unwrap();
+        assert_eq!(format!("{error:#}"), "line 2: unknown input event `hop`");
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{HashSet, VecDeque};
+
+use macroquad::{input::KeyCode, miniquad::KeyMods};
+
+use crate::utils::map_key;
+
+/// Modifier bits, as in GLFW.
+pub const MOD_SHIFT: u32 = 0x1;
+pub const MOD_CONTROL: u32 = 0x2;
+pub const MOD_ALT: u32 = 0x4;
+pub const MOD_SUPER: u32 = 0x8;
+
+/// Events kept for a cartridge that isn't polling; the oldest go first.
+const MAX_QUEUED_EVENTS: usize = 256;
+/// Bytes `input::poll_key_event` writes.
+pub const KEY_EVENT_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum KeyEventKind {
+    Press = 0,
+    Release = 1,
+    Repeat = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub kind: KeyEventKind,
+    /// LWJGL key code, -1 for keys it has none for.
+    pub key: i32,
+    /// The host's id for the physical key. Not a hardware scancode, but
+    /// stable for a key across runs.
+    pub scancode: i32,
+    pub mods: u32,
+}
+
+impl KeyEvent {
+    pub fn new(kind: KeyEventKind, keycode: KeyCode, mods: u32) -> Self {
+        Self {
+            kind,
+            key: lwjgl_key(keycode),
+            scancode: keycode as i32,
+            mods,
+        }
+    }
+
+    /// For sources that only know the LWJGL key code.
+    pub const fn from_lwjgl(kind: KeyEventKind, key: i32, mods: u32) -> Self {
+        Self {
+            kind,
+            key,
+            scancode: map_key(key) as i32,
+            mods,
+        }
+    }
+
+    /// Layout written to guest memory: kind, key, scancode and mods as
+    /// little endian 32-bit integers.
+    pub fn to_bytes(self) -> [u8; KEY_EVENT_SIZE] {
+        let mut bytes = [0; KEY_EVENT_SIZE];
+        bytes[0..4].copy_from_slice(&(self.kind as u32).to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.key.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.scancode.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.mods.to_le_bytes());
+        bytes
+    }
+}
+
+/// The LWJGL key code for `keycode`, the reverse of [`map_key`].
+pub fn lwjgl_key(keycode: KeyCode) -> i32 {
+    if keycode == KeyCode::Unknown {
+        return -1;
+    }
+    (32..=348)
+        .find(|&key| map_key(key) == keycode)
+        .unwrap_or(-1)
+}
+
+pub const fn modifier_bits(mods: KeyMods) -> u32 {
+    let mut bits = 0;
+    if mods.shift {
+        bits |= MOD_SHIFT;
+    }
+    if mods.ctrl {
+        bits |= MOD_CONTROL;
+    }
+    if mods.alt {
+        bits |= MOD_ALT;
+    }
+    if mods.logo {
+        bits |= MOD_SUPER;
+    }
+    bits
+}
+
+/// The LWJGL key code that types `ch`, for ASCII keys.
+pub fn char_key(ch: char) -> Option<i32> {
+    let code = i32::try_from(u32::from(ch.to_ascii_uppercase())).ok()?;
+    (ch.is_ascii_graphic() || ch == ' ')
+        .then_some(code)
+        .filter(|&code| lwjgl_key(map_key(code)) == code)
+}
+
+/// Parses a key given as a single character, an LWJGL key code, or a name
+/// like macroquad's `KeyCode` (`Space`, `LeftShift`, `Key1`; case and `_`
+/// don't matter).
+pub fn parse_key(text: &str) -> anyhow::Result<i32> {
+    let mut chars = text.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next())
+        && let Some(code) = char_key(ch)
+    {
+        return Ok(code);
+    }
+    if let Ok(code) = text.parse() {
+        return Ok(code);
+    }
+
+    let wanted = text.replace('_', "").to_ascii_lowercase();
+    (32..=348)
+        .find(|&code| {
+            let keycode = map_key(code);
+            lwjgl_key(keycode) == code && format!("{keycode:?}").to_ascii_lowercase() == wanted
+        })
+        .ok_or_else(|| anyhow::anyhow!("unknown key `{text}`"))
+}
+
+/// The name [`parse_key`] reads back, or the code for keys without one.
+pub fn key_name(key: i32) -> String {
+    let keycode = map_key(key);
+    if keycode == KeyCode::Unknown || lwjgl_key(keycode) != key {
+        return key.to_string();
+    }
+    format!("{keycode:?}")
+}
+
+/// Key events waiting for the cartridge, the keys held down, and the keys
+/// that went down or up since the last frame.
+#[derive(Default)]
+pub struct KeyboardEvents {
+    queue: VecDeque<KeyEvent>,
+    held: HashSet<i32>,
+    pressed: HashSet<i32>,
+    released: HashSet<i32>,
+}
+
+impl KeyboardEvents {
+    /// Starts a new frame for [`Self::pressed_this_frame`] and
+    /// [`Self::released_this_frame`]. Queued events stay until polled.
+    pub fn begin_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    pub fn push(&mut self, event: KeyEvent) {
+        match event.kind {
+            KeyEventKind::Press => {
+                self.pressed.insert(event.key);
+                self.held.insert(event.key);
+            }
+            KeyEventKind::Release => {
+                self.released.insert(event.key);
+                self.held.remove(&event.key);
+            }
+            KeyEventKind::Repeat => {
+                self.held.insert(event.key);
+            }
+        }
+
+        if self.queue.len() == MAX_QUEUED_EVENTS {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(event);
+    }
+
+    /// Keys held down, lowest code first.
+    pub fn held_keys(&self) -> Vec<i32> {
+        let mut held: Vec<i32> = self.held.iter().copied().collect();
+        held.sort_unstable();
+        held
+    }
+
+    pub fn poll(&mut self) -> Option<KeyEvent> {
+        self.queue.pop_front()
+    }
+
+    pub fn is_key_down(&self, key: i32) -> bool {
+        self.held.contains(&key)
+    }
+
+    pub fn pressed_this_frame(&self, key: i32) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    pub fn released_this_frame(&self, key: i32) -> bool {
+        self.released.contains(&key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lwjgl_key_round_trips() {
+        for key in [32, 65, 90, 256, 262, 290, 340] {
+            assert_eq!(lwjgl_key(map_key(key)), key);
+        }
+        assert_eq!(lwjgl_key(KeyCode::Unknown), -1);
+    }
+
+    #[test]
+    fn key_names() {
+        assert_eq!(key_name(340), "LeftShift");
+        assert_eq!(key_name(12), "12");
+        assert_eq!(parse_key("a").unwrap(), 65);
+        assert_eq!(parse_key("Left_Shift").unwrap(), 340);
+        assert_eq!(parse_key("up").unwrap(), 265);
+        assert_eq!(parse_key("key1").unwrap(), 49);
+        assert_eq!(parse_key("1").unwrap(), 49);
+        assert_eq!(parse_key("290").unwrap(), 290);
+        assert!(parse_key("nope").is_err());
+    }
+
+    #[test]
+    fn edges_last_one_frame() {
+        let mut events = KeyboardEvents::default();
+        events.push(KeyEvent::from_lwjgl(KeyEventKind::Press, 65, MOD_SHIFT));
+        events.push(KeyEvent::from_lwjgl(KeyEventKind::Repeat, 65, MOD_SHIFT));
+        assert!(events.pressed_this_frame(65));
+        assert!(!events.released_this_frame(65));
+
+        events.begin_frame();
+        events.push(KeyEvent::from_lwjgl(KeyEventKind::Release, 65, 0));
+        assert!(!events.pressed_this_frame(65));
+        assert!(events.released_this_frame(65));
+
+        let kinds: Vec<KeyEventKind> = std::iter::from_fn(|| events.poll())
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                KeyEventKind::Press,
+                KeyEventKind::Repeat,
+                KeyEventKind::Release
+            ]
+        );
+    }
+
+    #[test]
+    fn event_layout() {
+        let event = KeyEvent {
+            kind: KeyEventKind::Release,
+            key: 65,
+            scancode: 7,
+            mods: MOD_CONTROL | MOD_ALT,
+        };
+        let bytes = event.to_bytes();
+        assert_eq!(bytes[0..4], 1u32.to_le_bytes());
+        assert_eq!(bytes[4..8], 65i32.to_le_bytes());
+        assert_eq!(bytes[8..12], 7i32.to_le_bytes());
+        assert_eq!(bytes[12..16], 6u32.to_le_bytes());
+    }
+}
@@ -0,0 +1,137 @@
+//! User key remapping, between the key that is pressed and the key code the
+//! cartridge sees.
+//!
+//! Bindings are kept per cartridge in `<cartridge>.keymap` next to it, one
+//! `<pressed key> = <key the cartridge sees>` line each, with key names as
+//! [`parse_key`] reads them. Keys without a binding pass through unchanged.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::keyboard::{key_name, parse_key};
+
+#[derive(Default)]
+pub struct KeyMap {
+    /// LWJGL key codes, pressed key to the key the cartridge sees.
+    bindings: BTreeMap<i32, i32>,
+    path: Option<PathBuf>,
+}
+
+impl KeyMap {
+    pub fn path_for(cartridge: &Path) -> PathBuf {
+        cartridge.with_extension("keymap")
+    }
+
+    /// Loads the bindings for `cartridge`. A missing file means no bindings.
+    pub fn load(cartridge: &Path) -> anyhow::Result<Self> {
+        let path = Self::path_for(cartridge);
+        let mut keymap = if path.exists() {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            Self::parse(&text).with_context(|| format!("failed to load {}", path.display()))?
+        } else {
+            Self::default()
+        };
+        keymap.path = Some(path);
+        Ok(keymap)
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut keymap = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (from, to) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("line {}: expected `key = key`", i + 1))?;
+            let from = parse_key(from.trim()).with_context(|| format!("line {}", i + 1))?;
+            let to = parse_key(to.trim()).with_context(|| format!("line {}", i + 1))?;
+            keymap.bind(from, to);
+        }
+        Ok(keymap)
+    }
+
+    /// Writes the bindings back to the file they were loaded from.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        fs::write(path, self.to_text())
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::from("# pressed key = key the cartridge sees\n");
+        for (from, to) in self.bindings() {
+            let _ = writeln!(text, "{} = {}", key_name(from), key_name(to));
+        }
+        text
+    }
+
+    /// The key code the cartridge sees when `key` is pressed.
+    pub fn map(&self, key: i32) -> i32 {
+        self.bindings.get(&key).copied().unwrap_or(key)
+    }
+
+    /// Makes `from` act as `to`. Binding a key to itself removes its
+    /// binding.
+    pub fn bind(&mut self, from: i32, to: i32) {
+        if from == to {
+            self.bindings.remove(&from);
+        } else {
+            self.bindings.insert(from, to);
+        }
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = (i32, i32)> {
+        self.bindings.iter().map(|(&from, &to)| (from, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_bound_keys_only() {
+        let keymap = KeyMap::parse("# swap\nZ = Space\nleft_shift = 88\n").unwrap();
+        assert_eq!(keymap.map(90), 32);
+        assert_eq!(keymap.map(340), 88);
+        assert_eq!(keymap.map(65), 65);
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let mut keymap = KeyMap::default();
+        keymap.bind(87, 265);
+        keymap.bind(65, 263);
+        keymap.bind(68, 68);
+        assert_eq!(
+            keymap.to_text(),
+            "# pressed key = key the cartridge sees\nA = Left\nW = Up\n"
+        );
+        assert_eq!(
+            KeyMap::parse(&keymap.to_text())
+                .unwrap()
+                .bindings()
+                .collect::<Vec<_>>(),
+            [(65, 263), (87, 265)]
+        );
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        let error = KeyMap::parse("A = Left\nB Right\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 2: expected `key = key`");
+        assert!(KeyMap::parse("A = nope\n").is_err());
+    }
+}
@@ -4,29 +4,66 @@ use macroquad::{miniquad::window::order_quit, prelude::*};
 
 use crate::{
     audio_manager::get_raw_audio_manager,
+    cartridge::get_resolution,
+    chrome_trace::save_chrome_trace,
+    cli::{CliArgs, get_cli_args},
+    dev_console::run_script,
+    display::Display,
+    font::draw_queued_text,
     gpu::renderer::get_gpu_renderer,
-    profiler::{begin_profiler, end_profiler, get_profile_averages, rebegin_profiler},
+    host_calls::next_host_call_frame,
+    overlay::{
+        console::ConsoleOverlay, host_calls::HostCallOverlay, keymap::KeymapOverlay,
+        profiler::ProfilerOverlay,
+    },
+    profiler::{begin_profiler, end_profiler, next_profiler_frame, rebegin_profiler},
     storage::get_storage,
-    wasm::init_wasm,
+    utils::timestamped_path,
+    wasm::{WASMRuntime, init_wasm},
 };
 
 mod audio_manager;
+mod cartridge;
+mod cartridge_log;
+mod chrome_trace;
+mod cli;
+mod clock;
+mod dev_console;
+mod dirty_region;
+mod display;
+mod filters;
+mod font;
+mod gamepad;
 mod gpu;
+mod host_calls;
+mod input_source;
+mod keyboard;
+mod keymap;
 mod modules;
+mod mouse;
+mod overlay;
+mod panic_capture;
+mod pixel_format;
+mod present;
 mod profiler;
+mod sampler;
+mod save_state;
 mod storage;
+mod surface;
+mod terminal;
 mod utils;
 pub mod wasm;
+mod window_input;
 
-pub const SCREEN_WIDTH: i32 = 800;
-pub const SCREEN_HEIGHT: i32 = 600;
-
+#[allow(clippy::cast_possible_wrap)]
 fn window_conf() -> macroquad::conf::Conf {
+    let (window_width, window_height) = get_resolution().window_size();
+
     macroquad::conf::Conf {
         miniquad_conf: Conf {
             window_title: "Gooseboy Emulator".to_owned(),
-            window_width: SCREEN_WIDTH,
-            window_height: SCREEN_HEIGHT,
+            window_width: window_width as i32,
+            window_height: window_height as i32,
             window_resizable: false,
             ..Default::default()
         },
@@ -37,33 +74,102 @@ fn window_conf() -> macroquad::conf::Conf {
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    env_logger::builder()
+/// Where logs go in `--terminal` mode, since the terminal is showing the
+/// cartridge.
+const TERMINAL_LOG_PATH: &str = "gooseboy-emulator.log";
+
+fn init_logger(to_file: bool) {
+    let mut builder = env_logger::builder();
+    builder
         .filter_level(log::LevelFilter::Info)
         .filter(Some("gooseboy_emulator"), log::LevelFilter::Trace)
-        .init();
-
-    let path = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "tests/goosegpu.wasm".to_string());
-    let data = fs::read(path).expect("failed to open wasm file");
-    let mut wasm = init_wasm(data).expect("failed to init wasm");
-    log::info!("initialized!");
-    wasm.main().expect("failed to call main function");
-    log::info!("main function called!");
-
-    let fb_width = SCREEN_WIDTH as usize;
-    let fb_height = SCREEN_HEIGHT as usize;
-    let fb_size = fb_width * fb_height * 4;
-    let mut fb_buf = vec![0u8; fb_size];
-
-    #[allow(clippy::cast_possible_truncation)]
-    let texture = Texture2D::from_rgba8(fb_width as u16, fb_height as u16, &fb_buf);
-
-    if wasm.gpu_main().is_ok() {
+        // filtered by `--log-level` before it gets here
+        .filter(Some("cartridge"), log::LevelFilter::Trace);
+    if to_file {
+        match fs::File::create(TERMINAL_LOG_PATH) {
+            Ok(file) => {
+                builder.target(env_logger::Target::Pipe(Box::new(file)));
+            }
+            Err(e) => eprintln!("failed to create {TERMINAL_LOG_PATH}: {e}"),
+        }
+    }
+    builder.init();
+}
+
+/// Loads the cartridge and runs its `main`.
+fn start_cartridge(args: &CliArgs) -> WASMRuntime {
+    let data = fs::read(&args.cartridge).expect("failed to open wasm file");
+    let mut wasm = init_wasm(data, args).expect("failed to init wasm");
+    log::info!("initialized at {}!", get_resolution());
+    if wasm.main().expect("failed to call main function") {
+        log::info!("main function called!");
+    }
+    wasm
+}
+
+fn call_gpu_main(wasm: &mut WASMRuntime) {
+    if wasm.gpu_main().expect("failed to call gpu main function") {
         log::info!("gpu main function called!");
     }
+}
+
+/// Writes everything that is saved on exit.
+fn shutdown(wasm: &mut WASMRuntime, args: &CliArgs) {
+    if let Some(path) = &args.trace_path
+        && let Err(e) = save_chrome_trace(path)
+    {
+        log::error!("failed to write trace: {e}");
+    }
+
+    if let Some(path) = &args.guest_profile
+        && let Err(e) = wasm.save_guest_profile(path)
+    {
+        log::error!("failed to write guest profile: {e}");
+    }
+
+    get_storage().lock().write_to_disk();
+    wasm.store.get_mut().data_mut().close_input();
+}
+
+fn main() {
+    let args = get_cli_args();
+    // before `window_conf`, which reads the cartridge manifest
+    init_logger(args.terminal);
+    if !args.terminal {
+        macroquad::Window::from_config(window_conf(), run_window(args));
+        return;
+    }
+
+    let mut wasm = start_cartridge(args);
+    call_gpu_main(&mut wasm);
+    if let Some(path) = &args.console_script {
+        run_script(&mut wasm, path);
+    }
+    if let Err(e) = terminal::run(&mut wasm, &args.filters) {
+        log::error!("terminal session failed: {e:#}");
+        eprintln!("terminal session failed: {e:#}");
+    }
+    shutdown(&mut wasm, args);
+}
+
+async fn run_window(args: &'static CliArgs) {
+    let resolution = get_resolution();
+    let mut wasm = start_cartridge(args);
+
+    let mut fb_buf = vec![0u8; resolution.pixels() * 4];
+    let mut display = Display::new(resolution, args.filters.clone());
+
+    call_gpu_main(&mut wasm);
+
+    let mut profiler_overlay = ProfilerOverlay::new();
+    let mut host_call_overlay = HostCallOverlay::new();
+    let mut console_overlay = ConsoleOverlay::new();
+    let mut keymap_overlay = KeymapOverlay::new();
+    if let Some(path) = &args.console_script {
+        for line in run_script(&mut wasm, path) {
+            console_overlay.push_line(line);
+        }
+    }
 
     prevent_quit();
 
@@ -72,6 +178,22 @@ async fn main() {
             break;
         }
 
+        next_profiler_frame();
+        next_host_call_frame();
+        profiler_overlay.handle_input();
+        host_call_overlay.handle_input();
+        console_overlay.handle_input(&mut wasm);
+        keymap_overlay.handle_input(wasm.store.get_mut().data_mut());
+        if !wasm.store.get_mut().data_mut().poll_input() {
+            break;
+        }
+
+        if is_key_pressed(KeyCode::F5)
+            && let Err(e) = wasm.save_guest_profile(&timestamped_path("guest-profile", "json"))
+        {
+            log::error!("failed to write guest profile: {e}");
+        }
+
         begin_profiler("audio update");
         {
             get_raw_audio_manager().lock().update();
@@ -81,11 +203,12 @@ async fn main() {
         wasm.update().expect("wasm update failed");
 
         rebegin_profiler("copy framebuffer");
-        wasm.get_framebuffer_into(&mut fb_buf)
+        let dirty = wasm
+            .sync_framebuffer(&mut fb_buf)
             .expect("failed to fill framebuffer");
 
         rebegin_profiler("upload texture");
-        texture.update_from_bytes(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, &fb_buf);
+        display.upload(&fb_buf, dirty);
 
         rebegin_profiler("clear");
         clear_background(BLACK);
@@ -101,37 +224,19 @@ async fn main() {
         set_default_camera();
 
         rebegin_profiler("draw texture");
-        draw_texture(&texture, 0.0, 0.0, WHITE);
+        display.draw();
+        draw_queued_text(&wasm.take_queued_text(), resolution);
 
         rebegin_profiler("profiler");
-        let font_size = 24.0f32;
-        let color = Color::new(0.0, 1.0, 0.0, 0.5);
-        draw_text(
-            &format!("FPS: {}", get_fps()),
-            0.0,
-            font_size,
-            font_size,
-            color,
-        );
-        let mut avgs = get_profile_averages();
-        avgs.sort_by(|a, b| b.1.cmp(&a.1));
-
-        #[allow(clippy::cast_precision_loss)]
-        for (i, (label, avg)) in avgs.iter().enumerate() {
-            let avg_ms = avg.as_secs_f64() * 1000.0;
-            draw_text(
-                &format!("{label}: {avg_ms:6.3} ms"),
-                0.0,
-                font_size + font_size.mul_add(i as f32, font_size),
-                font_size,
-                color,
-            );
-        }
+        profiler_overlay.draw();
+        host_call_overlay.draw();
+        console_overlay.draw();
+        keymap_overlay.draw(wasm.store.get_mut().data());
         end_profiler();
 
         next_frame().await;
     }
 
-    get_storage().lock().write_to_disk();
+    shutdown(&mut wasm, args);
     order_quit();
 }
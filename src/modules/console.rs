@@ -1,23 +1,46 @@
-use wasmtime::Caller;
+use fast_cell::FastCell;
+use log::Level;
+use wasmtime::{Caller, Memory};
 
 use crate::wasm::{WASMHostState, WASMPointer, WASMRuntime};
 
+/// `console.log` predates levels and logs at info.
+const LEVELS: [(&str, Level); 6] = [
+    ("log", Level::Info),
+    ("trace", Level::Trace),
+    ("debug", Level::Debug),
+    ("info", Level::Info),
+    ("warn", Level::Warn),
+    ("error", Level::Error),
+];
+
 pub fn link_console(runtime: &WASMRuntime) -> anyhow::Result<()> {
-    let memory = runtime.memory.clone();
     runtime.linker.with(|linker| {
-        linker
-            .func_wrap(
+        for (name, level) in LEVELS {
+            let memory = runtime.memory.clone();
+            linker.func_wrap(
                 "console",
-                "log",
+                name,
                 move |mut caller: Caller<'_, WASMHostState>, ptr: WASMPointer, len: u32| {
-                    let mem = memory.with(|m| m.unwrap().data(&mut caller));
-                    let slice = &mem[ptr as usize..(ptr + len) as usize];
-                    let string = std::str::from_utf8(slice).unwrap_or("<invalid utf8>");
-                    println!("{string}");
+                    log_message(&memory, &mut caller, level, ptr, len);
                 },
-            )
-            .cloned()
+            )?;
+        }
+        anyhow::Ok(())
     })?;
 
     Ok(())
 }
+
+fn log_message(
+    memory: &FastCell<Option<Memory>>,
+    caller: &mut Caller<'_, WASMHostState>,
+    level: Level,
+    ptr: WASMPointer,
+    len: u32,
+) {
+    let (mem, state) = memory.with(|m| m.unwrap().data_and_store_mut(caller));
+    let slice = &mem[ptr as usize..(ptr + len) as usize];
+    let string = std::str::from_utf8(slice).unwrap_or("<invalid utf8>");
+    state.log.log(level, string);
+}
@@ -1,7 +1,10 @@
 use wasmtime::Caller;
 
 use crate::{
-    SCREEN_HEIGHT, SCREEN_WIDTH,
+    dirty_region::DirtyRect,
+    pixel_format::{PixelFormat, write_palette},
+    present::PresentedFrames,
+    surface::{BlitParams, Surface, guest_range},
     wasm::{WASMHostState, WASMPointer, WASMPointerMut, WASMRuntime},
 };
 
@@ -15,108 +18,208 @@ pub fn link_framebuffer(runtime: &WASMRuntime) -> anyhow::Result<()> {
         linker.func_wrap(
             "framebuffer",
             "get_framebuffer_width",
-            |_: Caller<'_, WASMHostState>| SCREEN_WIDTH as u32,
+            |caller: Caller<'_, WASMHostState>| caller.data().resolution.width,
         )?;
 
         linker.func_wrap(
             "framebuffer",
             "get_framebuffer_height",
-            |_: Caller<'_, WASMHostState>| SCREEN_HEIGHT as u32,
+            |caller: Caller<'_, WASMHostState>| caller.data().resolution.height,
+        )?;
+
+        linker.func_wrap(
+            "framebuffer",
+            "mark_dirty_rect",
+            |mut caller: Caller<'_, WASMHostState>, x: i32, y: i32, w: u32, h: u32| {
+                let state = caller.data_mut();
+                state.reports_dirty_rects = true;
+
+                let width = state.resolution.width as usize;
+                let height = state.resolution.height as usize;
+                let rect = DirtyRect::clipped(x, y, w, h, width, height);
+                if let Some(rect) = rect {
+                    state.dirty_rect = Some(state.dirty_rect.map_or(rect, |d| d.union(rect)));
+                }
+            },
+        )?;
+
+        linker.func_wrap(
+            "framebuffer",
+            "set_pixel_format",
+            |mut caller: Caller<'_, WASMHostState>, format: u32| {
+                let state = caller.data_mut();
+                state.pixel_format = PixelFormat::from_guest(format)?;
+                // frames presented in the old format can't be shown anymore
+                if let Some(frames) = &mut state.presented {
+                    *frames = PresentedFrames::default();
+                }
+                redraw_everything(state);
+                Ok(())
+            },
+        )?;
+
+        let memory2 = memory.clone();
+        linker.func_wrap(
+            "framebuffer",
+            "set_palette",
+            move |mut caller: Caller<'_, WASMHostState>,
+                  ptr: WASMPointer,
+                  first: u32,
+                  count: u32| {
+                let (mem, state) = memory2.with(|m| m.unwrap().data_and_store_mut(&mut caller));
+                let start = ptr as usize;
+                let colors = (count as usize)
+                    .checked_mul(4)
+                    .and_then(|len| mem.get(start..start.checked_add(len)?))
+                    .ok_or_else(|| anyhow::anyhow!("palette is out of bounds"))?;
+                write_palette(&mut state.palette, first as usize, colors)?;
+                redraw_everything(state);
+                Ok(())
+            },
+        )?;
+
+        let memory2 = memory.clone();
+        linker.func_wrap(
+            "framebuffer",
+            "present",
+            move |mut caller: Caller<'_, WASMHostState>, ptr: WASMPointer| {
+                let (mem, state) = memory2.with(|m| m.unwrap().data_and_store_mut(&mut caller));
+                let len = state.resolution.pixels() * state.pixel_format.bytes_per_pixel();
+                let frame = mem
+                    .get(ptr as usize..ptr as usize + len)
+                    .ok_or_else(|| anyhow::anyhow!("presented frame is out of bounds"))?;
+
+                state
+                    .presented
+                    .get_or_insert_with(|| {
+                        log::info!("cartridge presents its own frames");
+                        PresentedFrames::default()
+                    })
+                    .present(frame);
+                Ok(())
+            },
         )?;
 
         let memory2 = memory.clone();
         linker.func_wrap(
             "framebuffer",
             "blit_premultiplied_clipped",
-            #[allow(clippy::cast_sign_loss)]
             move |mut caller: Caller<'_, WASMHostState>,
                   dest_ptr: WASMPointerMut,
                   dest_w: u32,
                   dest_h: u32,
-                  dest_x: u32,
-                  dest_y: u32,
+                  dest_x: i32,
+                  dest_y: i32,
                   src_w: u32,
                   src_h: u32,
                   src_ptr: WASMPointer,
                   blend: i32| {
+                let blend = as_bool(blend, "blend")?;
                 let mem = memory2.with(|m| m.unwrap().data_mut(&mut caller));
-                let blend = match blend {
-                    0 => false,
-                    1 => true,
-                    _ => panic!("blend is not a boolean!"),
-                };
-
-                let src =
-                    &mut mem[src_ptr as usize..(src_ptr + (src_w * src_h * 4)) as usize].to_vec();
-                let dest = &mut mem[dest_ptr as usize..(dest_ptr + (dest_w * dest_h * 4)) as usize];
-                let surf_w = dest_w.cast_signed();
-                let surf_h = dest_h.cast_signed();
-
-                if src_w == 0 || src_h == 0 {
-                    return;
-                }
+                let (start, end) = guest_range(mem.len(), src_ptr, src_w, src_h)?;
+                let src = mem[start..end].to_vec();
+                Surface::from_guest(mem, dest_ptr, dest_w, dest_h)?.blit(
+                    &src,
+                    src_w as usize,
+                    src_h as usize,
+                    dest_x,
+                    dest_y,
+                    blend,
+                );
+                Ok(())
+            },
+        )?;
 
-                let src_left = dest_x;
-                let src_top = dest_y;
-                let src_right = dest_x + src_w;
-                let src_bottom = dest_y + src_h;
+        let memory2 = memory.clone();
+        linker.func_wrap(
+            "framebuffer",
+            "fill_rect",
+            move |mut caller: Caller<'_, WASMHostState>,
+                  ptr: WASMPointerMut,
+                  surface_w: u32,
+                  surface_h: u32,
+                  x: i32,
+                  y: i32,
+                  w: u32,
+                  h: u32,
+                  color: u32,
+                  blend: i32| {
+                let blend = as_bool(blend, "blend")?;
+                let mem = memory2.with(|m| m.unwrap().data_mut(&mut caller));
+                Surface::from_guest(mem, ptr, surface_w, surface_h)?
+                    .fill_rect(x, y, w, h, color, blend);
+                Ok(())
+            },
+        )?;
 
-                let vis_left = src_left;
-                let vis_top = src_top;
-                let vis_right = src_right.min(surf_w.try_into().unwrap());
-                let vis_bottom = src_bottom.min(surf_h.try_into().unwrap());
+        let memory2 = memory.clone();
+        linker.func_wrap(
+            "framebuffer",
+            "draw_line",
+            move |mut caller: Caller<'_, WASMHostState>,
+                  ptr: WASMPointerMut,
+                  surface_w: u32,
+                  surface_h: u32,
+                  x0: i32,
+                  y0: i32,
+                  x1: i32,
+                  y1: i32,
+                  color: u32,
+                  blend: i32| {
+                let blend = as_bool(blend, "blend")?;
+                let mem = memory2.with(|m| m.unwrap().data_mut(&mut caller));
+                Surface::from_guest(mem, ptr, surface_w, surface_h)?
+                    .draw_line(x0, y0, x1, y1, color, blend);
+                Ok(())
+            },
+        )?;
 
-                if vis_left >= vis_right || vis_top >= vis_bottom {
-                    return;
-                }
+        let memory2 = memory.clone();
+        linker.func_wrap(
+            "framebuffer",
+            "draw_circle",
+            move |mut caller: Caller<'_, WASMHostState>,
+                  ptr: WASMPointerMut,
+                  surface_w: u32,
+                  surface_h: u32,
+                  cx: i32,
+                  cy: i32,
+                  radius: u32,
+                  color: u32,
+                  filled: i32,
+                  blend: i32| {
+                let filled = as_bool(filled, "filled")?;
+                let blend = as_bool(blend, "blend")?;
+                let mem = memory2.with(|m| m.unwrap().data_mut(&mut caller));
+                Surface::from_guest(mem, ptr, surface_w, surface_h)?
+                    .draw_circle(cx, cy, radius, color, filled, blend);
+                Ok(())
+            },
+        )?;
 
-                let start_src_x = (vis_left - dest_x) as usize;
-                let start_src_y = (vis_top - dest_y) as usize;
-
-                let vis_w = (vis_right - vis_left) as usize;
-                let vis_h = (vis_bottom - vis_top) as usize;
-
-                for row in 0..vis_h {
-                    let dst_y = (vis_top as usize) + row;
-                    let src_row = (start_src_y + row) * (src_w as usize) * 4;
-                    for col in 0..vis_w {
-                        let dst_x = (vis_left as usize) + col;
-                        let sidx = src_row + (start_src_x + col) * 4;
-                        let didx = (dst_y * (dest_w as usize) + dst_x) * 4;
-
-                        let sa = src[sidx + 3];
-                        if sa == 0 {
-                            continue;
-                        }
-
-                        if !blend || sa == 255 {
-                            dest[didx..didx + 4].copy_from_slice(&src[sidx..sidx + 4]);
-                            continue;
-                        }
-
-                        let sa_u32 = u32::from(sa);
-                        let inv = 255u32 - sa_u32;
-
-                        let sr = u32::from(src[sidx]);
-                        let sg = u32::from(src[sidx + 1]);
-                        let sb = u32::from(src[sidx + 2]);
-
-                        let dr = u32::from(dest[didx]);
-                        let dg = u32::from(dest[didx + 1]);
-                        let db = u32::from(dest[didx + 2]);
-                        let da = u32::from(dest[didx + 3]);
-
-                        let out_r = sr + ((dr * inv + 127) / 255);
-                        let out_g = sg + ((dg * inv + 127) / 255);
-                        let out_b = sb + ((db * inv + 127) / 255);
-                        let out_a = sa_u32 + ((da * inv + 127) / 255);
-
-                        dest[didx] = out_r.min(255) as u8;
-                        dest[didx + 1] = out_g.min(255) as u8;
-                        dest[didx + 2] = out_b.min(255) as u8;
-                        dest[didx + 3] = out_a.min(255) as u8;
-                    }
-                }
+        let memory2 = memory.clone();
+        linker.func_wrap(
+            "framebuffer",
+            "blit_ex",
+            move |mut caller: Caller<'_, WASMHostState>,
+                  dest_ptr: WASMPointerMut,
+                  dest_w: u32,
+                  dest_h: u32,
+                  src_ptr: WASMPointer,
+                  src_w: u32,
+                  src_h: u32,
+                  params_ptr: WASMPointer| {
+                let mem = memory2.with(|m| m.unwrap().data_mut(&mut caller));
+                let params = BlitParams::read(mem, params_ptr)?;
+                let (start, end) = guest_range(mem.len(), src_ptr, src_w, src_h)?;
+                let src = mem[start..end].to_vec();
+                Surface::from_guest(mem, dest_ptr, dest_w, dest_h)?.blit_ex(
+                    &src,
+                    src_w as usize,
+                    src_h as usize,
+                    &params,
+                );
+                Ok(())
             },
         )?;
 
@@ -140,3 +243,26 @@ pub fn link_framebuffer(runtime: &WASMRuntime) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Makes the next sync upload the whole frame, for changes that affect every
+/// pixel without the cartridge touching its framebuffer.
+pub const fn redraw_everything(state: &mut WASMHostState) {
+    if let Some(frames) = &mut state.presented {
+        frames.redraw();
+    }
+    if state.reports_dirty_rects {
+        let resolution = state.resolution;
+        state.dirty_rect = Some(DirtyRect::full(
+            resolution.width as usize,
+            resolution.height as usize,
+        ));
+    }
+}
+
+fn as_bool(value: i32, name: &str) -> anyhow::Result<bool> {
+    match value {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => anyhow::bail!("{name} is not a boolean!"),
+    }
+}
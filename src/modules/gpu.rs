@@ -5,6 +5,8 @@ use crate::{
     wasm::{WASMHostState, WASMPointer, WASMRuntime},
 };
 
+/// Without a window there is no GL context for the renderer, so in
+/// `--terminal` mode these calls do nothing.
 pub fn link_gpu(runtime: &WASMRuntime) -> anyhow::Result<()> {
     runtime.linker.with(|linker| {
         let memory = runtime.memory.clone();
@@ -12,6 +14,9 @@ pub fn link_gpu(runtime: &WASMRuntime) -> anyhow::Result<()> {
             "gpu",
             "get_camera_transform",
             move |mut caller: Caller<'_, WASMHostState>, ptr: WASMPointer| {
+                if caller.data().is_headless() {
+                    return;
+                }
                 let mem = memory.with(|m| m.unwrap().data_mut(&mut caller));
                 get_gpu_renderer().lock().camera.write(mem, ptr);
             },
@@ -19,7 +24,10 @@ pub fn link_gpu(runtime: &WASMRuntime) -> anyhow::Result<()> {
         linker.func_wrap(
             "gpu",
             "set_camera_transform",
-            |_: Caller<'_, WASMHostState>, x: f32, y: f32, z: f32, yaw: f32, pitch: f32| {
+            |caller: Caller<'_, WASMHostState>, x: f32, y: f32, z: f32, yaw: f32, pitch: f32| {
+                if caller.data().is_headless() {
+                    return;
+                }
                 let camera = &mut get_gpu_renderer().lock().camera;
                 camera.read(x, y, z, yaw, pitch);
             },
@@ -29,6 +37,9 @@ pub fn link_gpu(runtime: &WASMRuntime) -> anyhow::Result<()> {
             "gpu",
             "submit_gpu_commands",
             move |mut caller: Caller<'_, WASMHostState>, ptr: WASMPointer, count: u32| {
+                if caller.data().is_headless() {
+                    return;
+                }
                 let mem = memory.with(|m| m.unwrap().data_mut(&mut caller));
 
                 let mut offset = ptr as usize;
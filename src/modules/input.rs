@@ -1,81 +1,225 @@
-use wasmtime::Caller;
+use fast_cell::FastCell;
+use wasmtime::{Caller, Linker, Memory};
 
 use crate::{
-    SCREEN_HEIGHT, SCREEN_WIDTH,
-    utils::{map_button, map_key},
-    wasm::{WASMHostState, WASMRuntime},
+    gamepad::GAMEPAD_EVENT_SIZE,
+    keyboard::KEY_EVENT_SIZE,
+    wasm::{WASMHostState, WASMPointerMut, WASMRuntime},
 };
 
 pub fn link_input(runtime: &WASMRuntime) -> anyhow::Result<()> {
     runtime.linker.with(|linker| {
-        linker.func_wrap("input", "get_key_code", |_: Caller<'_, WASMHostState>| {
-            if let Some(ch) = macroquad::prelude::get_char_pressed() {
-                return ch as i32;
-            }
-            -1i32
-        })?;
         linker.func_wrap(
             "input",
-            "get_key",
-            |_: Caller<'_, WASMHostState>, key: i32| {
-                i32::from(macroquad::prelude::is_key_down(map_key(key)))
+            "get_key_code",
+            |mut caller: Caller<'_, WASMHostState>| {
+                let ch = caller.data_mut().input.pop_char();
+                ch.map_or(-1, |ch| ch as i32)
             },
         )?;
         linker.func_wrap(
             "input",
-            "get_mouse_button",
-            |_: Caller<'_, WASMHostState>, button: i32| {
-                i32::from(macroquad::prelude::is_mouse_button_down(map_button(button)))
+            "get_key",
+            |caller: Caller<'_, WASMHostState>, key: i32| {
+                i32::from(caller.data().input.keyboard.is_key_down(key))
             },
         )?;
-        #[allow(clippy::cast_possible_truncation)]
-        linker.func_wrap("input", "get_mouse_x", |_: Caller<'_, WASMHostState>| {
-            macroquad::input::mouse_position().0 as i32
-        })?;
-        #[allow(clippy::cast_possible_truncation)]
-        linker.func_wrap("input", "get_mouse_y", |_: Caller<'_, WASMHostState>| {
-            macroquad::input::mouse_position().1 as i32
-        })?;
+        let memory = runtime.memory.clone();
         linker.func_wrap(
             "input",
-            "get_mouse_accumulated_dx",
-            |_: Caller<'_, WASMHostState>| {
-                f64::from(-macroquad::input::mouse_delta_position().x) * f64::from(SCREEN_WIDTH)
+            "poll_key_event",
+            move |mut caller: Caller<'_, WASMHostState>, ptr: WASMPointerMut| {
+                let (mem, state) = memory.with(|m| m.unwrap().data_and_store_mut(&mut caller));
+                let out = mem
+                    .get_mut(ptr as usize..ptr as usize + KEY_EVENT_SIZE)
+                    .ok_or_else(|| anyhow::anyhow!("key event is out of bounds"))?;
+                let Some(event) = state.input.keyboard.poll() else {
+                    return Ok(0i32);
+                };
+                out.copy_from_slice(&event.to_bytes());
+                Ok(1)
             },
         )?;
         linker.func_wrap(
             "input",
-            "get_mouse_accumulated_dy",
-            |_: Caller<'_, WASMHostState>| {
-                f64::from(-macroquad::input::mouse_delta_position().y) * f64::from(SCREEN_HEIGHT)
+            "is_key_pressed_this_frame",
+            |caller: Caller<'_, WASMHostState>, key: i32| {
+                i32::from(caller.data().input.keyboard.pressed_this_frame(key))
             },
         )?;
         linker.func_wrap(
             "input",
-            "is_mouse_grabbed",
-            |caller: Caller<'_, WASMHostState>| i32::from(caller.data().cursor_grabbed),
-        )?;
-        linker.func_wrap(
-            "input",
-            "grab_mouse",
-            |mut caller: Caller<'_, WASMHostState>| {
-                macroquad::input::show_mouse(false);
-                macroquad::input::set_cursor_grab(true);
-                caller.data_mut().cursor_grabbed = true;
+            "is_key_released_this_frame",
+            |caller: Caller<'_, WASMHostState>, key: i32| {
+                i32::from(caller.data().input.keyboard.released_this_frame(key))
             },
         )?;
-        linker
-            .func_wrap(
-                "input",
-                "release_mouse",
-                |mut caller: Caller<'_, WASMHostState>| {
-                    macroquad::input::show_mouse(true);
-                    macroquad::input::set_cursor_grab(false);
-                    caller.data_mut().cursor_grabbed = false;
-                },
-            )
-            .cloned()
+        link_gamepad(linker, &runtime.memory)?;
+        link_mouse(linker)
     })?;
 
     Ok(())
 }
+
+fn link_gamepad(
+    linker: &mut Linker<WASMHostState>,
+    memory: &FastCell<Option<Memory>>,
+) -> anyhow::Result<()> {
+    linker.func_wrap(
+        "input",
+        "is_gamepad_connected",
+        |caller: Caller<'_, WASMHostState>, pad: i32| {
+            i32::from(caller.data().gamepads.pad(pad).connected)
+        },
+    )?;
+    linker.func_wrap(
+        "input",
+        "get_gamepad_button",
+        |caller: Caller<'_, WASMHostState>, pad: i32, button: i32| {
+            i32::from(caller.data().gamepads.pad(pad).is_button_down(button))
+        },
+    )?;
+    linker.func_wrap(
+        "input",
+        "is_gamepad_button_pressed_this_frame",
+        |caller: Caller<'_, WASMHostState>, pad: i32, button: i32| {
+            i32::from(caller.data().gamepads.pressed_this_frame(pad, button))
+        },
+    )?;
+    linker.func_wrap(
+        "input",
+        "get_gamepad_axis",
+        |caller: Caller<'_, WASMHostState>, pad: i32, axis: i32| {
+            caller.data().gamepads.pad(pad).axis(axis)
+        },
+    )?;
+    linker.func_wrap(
+        "input",
+        "get_gamepad_trigger",
+        |caller: Caller<'_, WASMHostState>, pad: i32, trigger: i32| {
+            caller.data().gamepads.pad(pad).trigger(trigger)
+        },
+    )?;
+    let memory = memory.clone();
+    linker.func_wrap(
+        "input",
+        "poll_gamepad_event",
+        move |mut caller: Caller<'_, WASMHostState>, ptr: WASMPointerMut| {
+            let (mem, state) = memory.with(|m| m.unwrap().data_and_store_mut(&mut caller));
+            let out = mem
+                .get_mut(ptr as usize..ptr as usize + GAMEPAD_EVENT_SIZE)
+                .ok_or_else(|| anyhow::anyhow!("gamepad event is out of bounds"))?;
+            let Some(event) = state.gamepads.poll_event() else {
+                return Ok(0i32);
+            };
+            out.copy_from_slice(&event.to_bytes());
+            Ok(1)
+        },
+    )?;
+
+    Ok(())
+}
+
+fn link_mouse(linker: &mut Linker<WASMHostState>) -> anyhow::Result<()> {
+    linker.func_wrap(
+        "input",
+        "get_mouse_button",
+        |caller: Caller<'_, WASMHostState>, button: i32| {
+            i32::from(caller.data().input.mouse.is_button_down(button))
+        },
+    )?;
+    // positions are in framebuffer pixels, whatever the window scale
+    #[allow(clippy::cast_possible_truncation)]
+    linker.func_wrap(
+        "input",
+        "get_mouse_x",
+        |caller: Caller<'_, WASMHostState>| caller.data().input.mouse.position().0 as i32,
+    )?;
+    #[allow(clippy::cast_possible_truncation)]
+    linker.func_wrap(
+        "input",
+        "get_mouse_y",
+        |caller: Caller<'_, WASMHostState>| caller.data().input.mouse.position().1 as i32,
+    )?;
+    linker.func_wrap(
+        "input",
+        "get_mouse_accumulated_dx",
+        |mut caller: Caller<'_, WASMHostState>| caller.data_mut().input.mouse.take_delta_x(),
+    )?;
+    linker.func_wrap(
+        "input",
+        "get_mouse_accumulated_dy",
+        |mut caller: Caller<'_, WASMHostState>| caller.data_mut().input.mouse.take_delta_y(),
+    )?;
+    linker.func_wrap(
+        "input",
+        "get_mouse_wheel_dx",
+        |caller: Caller<'_, WASMHostState>| caller.data().input.mouse.wheel().0,
+    )?;
+    linker.func_wrap(
+        "input",
+        "get_mouse_wheel_dy",
+        |caller: Caller<'_, WASMHostState>| caller.data().input.mouse.wheel().1,
+    )?;
+    linker.func_wrap(
+        "input",
+        "is_mouse_in_window",
+        |caller: Caller<'_, WASMHostState>| i32::from(caller.data().input.mouse.is_inside()),
+    )?;
+    link_cursor(linker)
+}
+
+fn link_cursor(linker: &mut Linker<WASMHostState>) -> anyhow::Result<()> {
+    linker.func_wrap(
+        "input",
+        "is_mouse_grabbed",
+        |caller: Caller<'_, WASMHostState>| i32::from(caller.data().cursor_grabbed),
+    )?;
+    linker.func_wrap(
+        "input",
+        "grab_mouse",
+        |mut caller: Caller<'_, WASMHostState>| {
+            if !caller.data().is_headless() {
+                macroquad::input::show_mouse(false);
+                macroquad::input::set_cursor_grab(true);
+            }
+            let state = caller.data_mut();
+            state.input.mouse.clear_delta();
+            state.input_source.reset_motion();
+            state.cursor_grabbed = true;
+        },
+    )?;
+    linker.func_wrap(
+        "input",
+        "release_mouse",
+        |mut caller: Caller<'_, WASMHostState>| {
+            if !caller.data().is_headless() {
+                macroquad::input::show_mouse(!caller.data().cursor_hidden);
+                macroquad::input::set_cursor_grab(false);
+            }
+            let state = caller.data_mut();
+            state.input.mouse.clear_delta();
+            state.input_source.reset_motion();
+            state.cursor_grabbed = false;
+        },
+    )?;
+    linker.func_wrap(
+        "input",
+        "set_cursor_visible",
+        |mut caller: Caller<'_, WASMHostState>, visible: i32| {
+            let state = caller.data_mut();
+            state.cursor_hidden = visible == 0;
+            // a grabbed cursor stays hidden until it is released
+            if !state.is_headless() && !state.cursor_grabbed {
+                macroquad::input::show_mouse(visible != 0);
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "input",
+        "is_cursor_visible",
+        |caller: Caller<'_, WASMHostState>| i32::from(!caller.data().cursor_hidden),
+    )?;
+
+    Ok(())
+}
@@ -4,5 +4,7 @@ pub mod framebuffer;
 pub mod gpu;
 pub mod input;
 pub mod memory;
+pub mod profiler;
 pub mod storage;
 pub mod system;
+pub mod text;
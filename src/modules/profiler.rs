@@ -0,0 +1,68 @@
+use wasmtime::Caller;
+
+use crate::{
+    profiler::{begin_guest_profiler, end_profiler, profiler_counter_add, profiler_counter_set},
+    wasm::{WASMHostState, WASMPointer, WASMRuntime},
+};
+
+pub fn link_profiler(runtime: &WASMRuntime) -> anyhow::Result<()> {
+    runtime.linker.with(|linker| {
+        let memory = runtime.memory.clone();
+        linker.func_wrap(
+            "profiler",
+            "begin_scope",
+            move |mut caller: Caller<'_, WASMHostState>, ptr: WASMPointer, len: u32| {
+                let mem = memory.with(|m| m.unwrap().data(&mut caller));
+                let slice = &mem[ptr as usize..(ptr + len) as usize];
+                let label = std::str::from_utf8(slice).unwrap_or("<invalid utf8>");
+                begin_guest_profiler(label);
+                caller.data_mut().guest_scope_depth += 1;
+            },
+        )?;
+        linker.func_wrap(
+            "profiler",
+            "end_scope",
+            |mut caller: Caller<'_, WASMHostState>| {
+                // never let the guest close the emulator's own scopes
+                let state = caller.data_mut();
+                if state.guest_scope_depth > 0 {
+                    state.guest_scope_depth -= 1;
+                    end_profiler();
+                }
+            },
+        )?;
+        let memory = runtime.memory.clone();
+        linker.func_wrap(
+            "profiler",
+            "counter_add",
+            move |mut caller: Caller<'_, WASMHostState>, ptr: WASMPointer, len: u32, delta: f64| {
+                let mem = memory.with(|m| m.unwrap().data(&mut caller));
+                let slice = &mem[ptr as usize..(ptr + len) as usize];
+                let name = std::str::from_utf8(slice).unwrap_or("<invalid utf8>");
+                if delta.is_finite() {
+                    profiler_counter_add(name, delta);
+                }
+            },
+        )?;
+        let memory = runtime.memory.clone();
+        linker
+            .func_wrap(
+                "profiler",
+                "counter_set",
+                move |mut caller: Caller<'_, WASMHostState>,
+                      ptr: WASMPointer,
+                      len: u32,
+                      value: f64| {
+                    let mem = memory.with(|m| m.unwrap().data(&mut caller));
+                    let slice = &mem[ptr as usize..(ptr + len) as usize];
+                    let name = std::str::from_utf8(slice).unwrap_or("<invalid utf8>");
+                    if value.is_finite() {
+                        profiler_counter_set(name, value);
+                    }
+                },
+            )
+            .cloned()
+    })?;
+
+    Ok(())
+}
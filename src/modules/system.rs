@@ -1,9 +1,6 @@
 use wasmtime::Caller;
 
-use crate::{
-    utils::get_time_nanos,
-    wasm::{WASMHostState, WASMRuntime},
-};
+use crate::wasm::{WASMHostState, WASMRuntime};
 
 pub fn link_system(runtime: &WASMRuntime) -> anyhow::Result<()> {
     runtime.linker.with(|linker| {
@@ -16,8 +13,7 @@ pub fn link_system(runtime: &WASMRuntime) -> anyhow::Result<()> {
             .func_wrap(
                 "system",
                 "get_time_nanos",
-                #[allow(clippy::cast_possible_truncation)]
-                |_: Caller<'_, WASMHostState>| get_time_nanos(),
+                |mut caller: Caller<'_, WASMHostState>| caller.data_mut().clock.now(),
             )
             .cloned()
     })?;
@@ -0,0 +1,134 @@
+use wasmtime::Caller;
+
+use crate::{
+    font::{BitmapFont, FontId, QueuedText, get_font_registry},
+    surface::{Surface, guest_range},
+    wasm::{WASMHostState, WASMPointer, WASMPointerMut, WASMRuntime},
+};
+
+/// Copies a UTF-8 string out of guest memory, replacing invalid sequences.
+fn read_text(mem: &[u8], ptr: WASMPointer, len: u32) -> anyhow::Result<String> {
+    let slice = mem
+        .get(ptr as usize..ptr as usize + len as usize)
+        .ok_or_else(|| anyhow::anyhow!("text is out of bounds"))?;
+    Ok(String::from_utf8_lossy(slice).into_owned())
+}
+
+#[allow(clippy::too_many_lines)]
+pub fn link_text(runtime: &WASMRuntime) -> anyhow::Result<()> {
+    runtime.linker.with(|linker| {
+        let memory = runtime.memory.clone();
+        linker.func_wrap(
+            "text",
+            "draw_text",
+            move |mut caller: Caller<'_, WASMHostState>,
+                  ptr: WASMPointerMut,
+                  surface_w: u32,
+                  surface_h: u32,
+                  x: i32,
+                  y: i32,
+                  text_ptr: WASMPointer,
+                  text_len: u32,
+                  color: u32,
+                  scale: u32,
+                  font: FontId| {
+                let mem = memory.with(|m| m.unwrap().data_mut(&mut caller));
+                let text = read_text(mem, text_ptr, text_len)?;
+                let fonts = get_font_registry().lock();
+                let font = fonts
+                    .find_font(font)
+                    .ok_or_else(|| anyhow::anyhow!("font with id {font} doesn't exist"))?;
+
+                let mut surface = Surface::from_guest(mem, ptr, surface_w, surface_h)?;
+                font.draw(&mut surface, &text, x, y, color, scale, true);
+                drop(fonts);
+                Ok(())
+            },
+        )?;
+
+        let memory = runtime.memory.clone();
+        linker.func_wrap(
+            "text",
+            "measure_text",
+            move |mut caller: Caller<'_, WASMHostState>,
+                  text_ptr: WASMPointer,
+                  text_len: u32,
+                  scale: u32,
+                  font: FontId,
+                  out_ptr: WASMPointerMut| {
+                let mem = memory.with(|m| m.unwrap().data_mut(&mut caller));
+                let text = read_text(mem, text_ptr, text_len)?;
+                let fonts = get_font_registry().lock();
+                let font = fonts
+                    .find_font(font)
+                    .ok_or_else(|| anyhow::anyhow!("font with id {font} doesn't exist"))?;
+                let (width, height) = font.measure(&text, scale);
+                drop(fonts);
+
+                let out = mem
+                    .get_mut(out_ptr as usize..out_ptr as usize + 8)
+                    .ok_or_else(|| anyhow::anyhow!("measure_text output is out of bounds"))?;
+                out[..4].copy_from_slice(&width.to_le_bytes());
+                out[4..].copy_from_slice(&height.to_le_bytes());
+                Ok(())
+            },
+        )?;
+
+        let memory = runtime.memory.clone();
+        linker.func_wrap(
+            "text",
+            "register_font",
+            move |mut caller: Caller<'_, WASMHostState>,
+                  atlas_ptr: WASMPointer,
+                  atlas_w: u32,
+                  atlas_h: u32,
+                  glyph_w: u32,
+                  glyph_h: u32,
+                  first_char: u32,
+                  glyph_count: u32| {
+                let mem = memory.with(|m| m.unwrap().data(&mut caller));
+                let (start, end) = guest_range(mem.len(), atlas_ptr, atlas_w, atlas_h)?;
+                let font = BitmapFont::from_atlas(
+                    mem[start..end].to_vec(),
+                    atlas_w as usize,
+                    atlas_h as usize,
+                    glyph_w as usize,
+                    glyph_h as usize,
+                    first_char,
+                    glyph_count,
+                )?;
+                Ok(get_font_registry().lock().register(font))
+            },
+        )?;
+
+        let memory = runtime.memory.clone();
+        linker
+            .func_wrap(
+                "text",
+                "queue_text",
+                move |mut caller: Caller<'_, WASMHostState>,
+                      x: i32,
+                      y: i32,
+                      text_ptr: WASMPointer,
+                      text_len: u32,
+                      color: u32,
+                      scale: u32,
+                      font: FontId| {
+                    let mem = memory.with(|m| m.unwrap().data(&mut caller));
+                    let text = read_text(mem, text_ptr, text_len)?;
+                    caller.data_mut().queued_text.push(QueuedText {
+                        x,
+                        y,
+                        text,
+                        color,
+                        scale,
+                        font,
+                    });
+                    Ok(())
+                },
+            )
+            .cloned()
+    })?;
+
+    Ok(())
+}
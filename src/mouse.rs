@@ -0,0 +1,146 @@
+/// LWJGL button ids go up to 7 (`GLFW_MOUSE_BUTTON_LAST`).
+const BUTTON_COUNT: i32 = 8;
+
+/// How relative mouse motion is scaled before the cartridge reads it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MouseSettings {
+    pub sensitivity: f64,
+    pub invert_y: bool,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 1.0,
+            invert_y: false,
+        }
+    }
+}
+
+/// Mouse state gathered from input events between updates.
+#[derive(Default)]
+pub struct MouseEvents {
+    pub settings: MouseSettings,
+    /// In framebuffer pixels.
+    position: (f32, f32),
+    /// Relative motion the cartridge hasn't read yet.
+    delta: (f64, f64),
+    wheel_x: f64,
+    wheel_y: f64,
+    /// Bit `n` is set while LWJGL button `n` is down.
+    buttons: u8,
+    inside: bool,
+}
+
+impl MouseEvents {
+    /// Starts a new frame: wheel movement counts from here on.
+    pub const fn begin_frame(&mut self) {
+        self.wheel_x = 0.0;
+        self.wheel_y = 0.0;
+    }
+
+    /// Adds wheel movement in notches, positive is right and up.
+    pub fn scroll(&mut self, x: f64, y: f64) {
+        self.wheel_x += x;
+        self.wheel_y += y;
+    }
+
+    pub const fn wheel(&self) -> (f64, f64) {
+        (self.wheel_x, self.wheel_y)
+    }
+
+    pub const fn set_button(&mut self, button: i32, down: bool) {
+        if button < 0 || button >= BUTTON_COUNT {
+            return;
+        }
+        if down {
+            self.buttons |= 1 << button;
+        } else {
+            self.buttons &= !(1 << button);
+        }
+    }
+
+    pub const fn is_button_down(&self, button: i32) -> bool {
+        button >= 0 && button < BUTTON_COUNT && self.buttons & (1 << button) != 0
+    }
+
+    /// Notes where the cursor moved to, in framebuffer pixels.
+    pub const fn move_to(&mut self, x: f32, y: f32, inside: bool) {
+        self.position = (x, y);
+        self.inside = inside;
+    }
+
+    pub const fn position(&self) -> (f32, f32) {
+        self.position
+    }
+
+    /// Adds relative motion, scaled by the user's settings.
+    pub fn add_delta(&mut self, dx: f64, dy: f64) {
+        let dy = if self.settings.invert_y { -dy } else { dy };
+        self.delta.0 += dx * self.settings.sensitivity;
+        self.delta.1 += dy * self.settings.sensitivity;
+    }
+
+    /// Motion along x since the last call, for `get_mouse_accumulated_dx`.
+    pub const fn take_delta_x(&mut self) -> f64 {
+        std::mem::replace(&mut self.delta.0, 0.0)
+    }
+
+    /// Motion along y since the last call, for `get_mouse_accumulated_dy`.
+    pub const fn take_delta_y(&mut self) -> f64 {
+        std::mem::replace(&mut self.delta.1, 0.0)
+    }
+
+    pub const fn clear_delta(&mut self) {
+        self.delta = (0.0, 0.0);
+    }
+
+    pub const fn is_inside(&self) -> bool {
+        self.inside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wheel_resets_each_frame() {
+        let mut mouse = MouseEvents::default();
+        mouse.scroll(0.0, 1.0);
+        mouse.scroll(-1.0, 2.0);
+        assert_eq!(mouse.wheel(), (-1.0, 3.0));
+
+        mouse.begin_frame();
+        assert_eq!(mouse.wheel(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn delta_accumulates_until_read() {
+        let mut mouse = MouseEvents {
+            settings: MouseSettings {
+                sensitivity: 0.5,
+                invert_y: true,
+            },
+            ..MouseEvents::default()
+        };
+        mouse.add_delta(4.0, 2.0);
+        mouse.begin_frame();
+        mouse.add_delta(2.0, 2.0);
+        assert_eq!((mouse.take_delta_x(), mouse.take_delta_y()), (3.0, -2.0));
+        assert_eq!((mouse.take_delta_x(), mouse.take_delta_y()), (0.0, 0.0));
+    }
+
+    #[test]
+    fn buttons() {
+        let mut mouse = MouseEvents::default();
+        mouse.set_button(4, true);
+        mouse.set_button(0, true);
+        mouse.set_button(0, false);
+        mouse.set_button(9, true);
+        assert!(mouse.is_button_down(4));
+        assert!(!mouse.is_button_down(0));
+        assert!(!mouse.is_button_down(9));
+        assert!(!mouse.is_button_down(-1));
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use crate::{
+    dev_console::run_command,
+    wasm::{WASMHostState, WASMRuntime},
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::GraveAccent;
+const FONT_SIZE: f32 = 20.0;
+const VISIBLE_ROWS: usize = 14;
+const MAX_LINES: usize = 1000;
+
+/// Drop-down console showing cartridge log output and running
+/// [`crate::dev_console`] commands.
+pub struct ConsoleOverlay {
+    pub visible: bool,
+    input: String,
+    lines: VecDeque<String>,
+    history: Vec<String>,
+    /// Entry of `history` shown in the input line while browsing it.
+    history_index: Option<usize>,
+    /// Lines scrolled back from the bottom.
+    scroll: usize,
+    /// Cartridge log messages already copied into `lines`.
+    seen_log: u64,
+}
+
+impl ConsoleOverlay {
+    pub const fn new() -> Self {
+        Self {
+            visible: false,
+            input: String::new(),
+            lines: VecDeque::new(),
+            history: Vec::new(),
+            history_index: None,
+            scroll: 0,
+            seen_log: 0,
+        }
+    }
+
+    pub fn push_line(&mut self, line: String) {
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Copies cartridge log messages written since the last call.
+    fn pull_log(&mut self, state: &WASMHostState) {
+        let log = &state.log;
+        let new = usize::try_from(log.written() - self.seen_log).unwrap_or(usize::MAX);
+        let skip = log.recent().len().saturating_sub(new);
+        let lines: Vec<String> = log.recent().iter().skip(skip).cloned().collect();
+        self.seen_log = log.written();
+        for line in lines {
+            self.push_line(line);
+        }
+    }
+
+    /// Takes keyboard input while visible, so typing doesn't reach the
+    /// cartridge.
+    pub fn handle_input(&mut self, wasm: &mut WASMRuntime) {
+        let state = wasm.store.get_mut().data_mut();
+        self.pull_log(state);
+
+        // another host screen may have the keyboard already
+        if is_key_pressed(TOGGLE_KEY) && (self.visible || !state.keyboard_captured) {
+            self.set_visible(state, !self.visible);
+            // don't type the backtick that opened or closed the console
+            while get_char_pressed().is_some() {}
+            return;
+        }
+        if !self.visible {
+            return;
+        }
+
+        while let Some(ch) = get_char_pressed() {
+            if !ch.is_control() {
+                self.input.push(ch);
+            }
+        }
+
+        if is_key_pressed(KeyCode::Backspace) {
+            self.input.pop();
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.browse_history(true);
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.browse_history(false);
+        }
+        if is_key_pressed(KeyCode::PageUp) {
+            self.scroll = (self.scroll + VISIBLE_ROWS).min(self.lines.len());
+        }
+        if is_key_pressed(KeyCode::PageDown) {
+            self.scroll = self.scroll.saturating_sub(VISIBLE_ROWS);
+        }
+        if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+            self.submit(wasm);
+        }
+    }
+
+    fn set_visible(&mut self, state: &mut WASMHostState, visible: bool) {
+        self.visible = visible;
+        state.set_keyboard_captured(visible);
+    }
+
+    fn browse_history(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+        let index = match (self.history_index, older) {
+            (None, true) => Some(self.history.len() - 1),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) => (i + 1 < self.history.len()).then_some(i + 1),
+        };
+        self.history_index = index;
+        self.input = index.map_or_else(String::new, |i| self.history[i].clone());
+    }
+
+    fn submit(&mut self, wasm: &mut WASMRuntime) {
+        let line = std::mem::take(&mut self.input);
+        let line = line.trim();
+        self.history_index = None;
+        self.scroll = 0;
+        if line.is_empty() {
+            return;
+        }
+        if self.history.last().is_none_or(|last| last != line) {
+            self.history.push(line.to_string());
+        }
+
+        if line == "clear" {
+            self.lines.clear();
+            return;
+        }
+        self.push_line(format!("> {line}"));
+        match run_command(wasm, line) {
+            Ok(output) => {
+                for line in output {
+                    self.push_line(line);
+                }
+            }
+            Err(e) => self.push_line(format!("error: {e:#}")),
+        }
+    }
+
+    pub fn draw(&self) {
+        if !self.visible {
+            return;
+        }
+
+        let color = Color::new(1.0, 1.0, 1.0, 0.9);
+        let background = Color::new(0.0, 0.0, 0.0, 0.8);
+        #[allow(clippy::cast_precision_loss)]
+        let height = FONT_SIZE.mul_add((VISIBLE_ROWS + 1) as f32, 8.0);
+        draw_rectangle(0.0, 0.0, screen_width(), height, background);
+
+        let end = self.lines.len() - self.scroll;
+        let start = end.saturating_sub(VISIBLE_ROWS);
+        #[allow(clippy::cast_precision_loss)]
+        for (row, line) in self.lines.range(start..end).enumerate() {
+            draw_text(line, 4.0, FONT_SIZE * (row + 1) as f32, FONT_SIZE, color);
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let input_y = FONT_SIZE * (VISIBLE_ROWS + 1) as f32;
+        draw_text(
+            &format!("> {}_", self.input),
+            4.0,
+            input_y,
+            FONT_SIZE,
+            Color::new(1.0, 1.0, 0.0, 1.0),
+        );
+    }
+}
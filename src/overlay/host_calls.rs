@@ -0,0 +1,75 @@
+use macroquad::prelude::*;
+
+use crate::host_calls::get_host_call_tracer;
+
+const TOGGLE_KEY: KeyCode = KeyCode::F6;
+const FONT_SIZE: f32 = 20.0;
+const MAX_ROWS: usize = 20;
+
+pub struct HostCallOverlay {
+    pub visible: bool,
+}
+
+impl HostCallOverlay {
+    pub const fn new() -> Self {
+        Self { visible: false }
+    }
+
+    pub fn handle_input(&mut self) {
+        if is_key_pressed(TOGGLE_KEY) {
+            self.visible = !self.visible;
+        }
+    }
+
+    pub fn draw(&self) {
+        if !self.visible {
+            return;
+        }
+
+        let color = Color::new(0.0, 1.0, 1.0, 0.8);
+        let background = Color::new(0.0, 0.0, 0.0, 0.6);
+        let stats = get_host_call_tracer().lock().last_frame_stats();
+        let rows = stats.len().min(MAX_ROWS);
+
+        #[allow(clippy::cast_precision_loss)]
+        let height = FONT_SIZE.mul_add((rows + 2) as f32, 8.0);
+        let top = screen_height() - height;
+        draw_rectangle(0.0, top, 620.0, height, background);
+
+        let calls: u64 = stats.iter().map(|(_, s)| s.count).sum();
+        draw_text(
+            &format!("host calls last frame: {calls}  (F6 to hide)"),
+            4.0,
+            top + FONT_SIZE,
+            FONT_SIZE,
+            color,
+        );
+        draw_text(
+            &format!(
+                "{:<40} {:>8} {:>10} {:>10}",
+                "function", "calls", "total ms", "avg us"
+            ),
+            4.0,
+            FONT_SIZE.mul_add(2.0, top),
+            FONT_SIZE,
+            color,
+        );
+
+        #[allow(clippy::cast_precision_loss)]
+        for (i, (label, call)) in stats.iter().take(MAX_ROWS).enumerate() {
+            let total = call.total.as_secs_f64();
+            draw_text(
+                &format!(
+                    "{label:<40} {:>8} {:>10.3} {:>10.3}",
+                    call.count,
+                    total * 1000.0,
+                    total * 1_000_000.0 / (call.count as f64),
+                ),
+                4.0,
+                FONT_SIZE.mul_add(i as f32 + 3.0, top),
+                FONT_SIZE,
+                color,
+            );
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use macroquad::prelude::*;
+
+use crate::{
+    keyboard::{key_name, lwjgl_key},
+    wasm::WASMHostState,
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F7;
+const FONT_SIZE: f32 = 20.0;
+const MAX_ROWS: usize = 16;
+
+enum Step {
+    /// Waiting for the key to remap.
+    PickKey,
+    /// Waiting for the key the picked one should act as.
+    PickTarget(i32),
+}
+
+/// Binding screen for the cartridge's [`crate::keymap::KeyMap`]. Keys don't
+/// reach the cartridge while it is open, and every change is saved straight
+/// away.
+pub struct KeymapOverlay {
+    pub visible: bool,
+    step: Step,
+    status: String,
+}
+
+impl KeymapOverlay {
+    pub const fn new() -> Self {
+        Self {
+            visible: false,
+            step: Step::PickKey,
+            status: String::new(),
+        }
+    }
+
+    pub fn handle_input(&mut self, state: &mut WASMHostState) {
+        // another host screen may have the keyboard already
+        if is_key_pressed(TOGGLE_KEY) && (self.visible || !state.keyboard_captured) {
+            self.set_visible(state, !self.visible);
+            return;
+        }
+        if !self.visible {
+            return;
+        }
+
+        let Some(keycode) = get_last_key_pressed() else {
+            return;
+        };
+        let key = lwjgl_key(keycode);
+        match self.step {
+            Step::PickKey if keycode == KeyCode::Escape => self.set_visible(state, false),
+            Step::PickTarget(_) if keycode == KeyCode::Escape => {
+                self.step = Step::PickKey;
+                self.status.clear();
+            }
+            _ if key == -1 => self.status = format!("{keycode:?} can't be bound"),
+            Step::PickKey => {
+                self.step = Step::PickTarget(key);
+                self.status.clear();
+            }
+            Step::PickTarget(from) => {
+                state.keymap.bind(from, key);
+                self.step = Step::PickKey;
+                self.status = match state.keymap.save() {
+                    Ok(()) if from == key => format!("{} is no longer remapped", key_name(from)),
+                    Ok(()) => format!("{} now acts as {}", key_name(from), key_name(key)),
+                    Err(e) => {
+                        log::error!("failed to save key bindings: {e:#}");
+                        format!("failed to save: {e:#}")
+                    }
+                };
+            }
+        }
+    }
+
+    fn set_visible(&mut self, state: &mut WASMHostState, visible: bool) {
+        self.visible = visible;
+        self.step = Step::PickKey;
+        self.status.clear();
+        state.set_keyboard_captured(visible);
+    }
+
+    pub fn draw(&self, state: &WASMHostState) {
+        if !self.visible {
+            return;
+        }
+
+        let color = Color::new(1.0, 1.0, 1.0, 0.9);
+        let highlight = Color::new(1.0, 1.0, 0.0, 1.0);
+        let background = Color::new(0.0, 0.0, 0.0, 0.8);
+        let bindings: Vec<(i32, i32)> = state.keymap.bindings().collect();
+        let rows = bindings.len().min(MAX_ROWS);
+
+        #[allow(clippy::cast_precision_loss)]
+        let height = FONT_SIZE.mul_add((rows + 4) as f32, 8.0);
+        draw_rectangle(0.0, 0.0, screen_width(), height, background);
+
+        draw_text(
+            "key bindings for this cartridge  (F7 to close)",
+            4.0,
+            FONT_SIZE,
+            FONT_SIZE,
+            color,
+        );
+        let prompt = match self.step {
+            Step::PickKey => "press a key to remap it".to_string(),
+            Step::PickTarget(from) => format!(
+                "{} should act as: press a key, {} again to reset, Escape to cancel",
+                key_name(from),
+                key_name(from)
+            ),
+        };
+        draw_text(&prompt, 4.0, FONT_SIZE * 2.0, FONT_SIZE, highlight);
+        draw_text(&self.status, 4.0, FONT_SIZE * 3.0, FONT_SIZE, color);
+
+        if bindings.is_empty() {
+            draw_text(
+                "no keys are remapped",
+                4.0,
+                FONT_SIZE * 4.0,
+                FONT_SIZE,
+                color,
+            );
+        }
+        #[allow(clippy::cast_precision_loss)]
+        for (row, (from, to)) in bindings.iter().take(MAX_ROWS).enumerate() {
+            draw_text(
+                &format!("{:<16} -> {}", key_name(*from), key_name(*to)),
+                4.0,
+                FONT_SIZE * (row + 4) as f32,
+                FONT_SIZE,
+                color,
+            );
+        }
+    }
+}
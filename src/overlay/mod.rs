@@ -0,0 +1,4 @@
+pub mod console;
+pub mod host_calls;
+pub mod keymap;
+pub mod profiler;
@@ -0,0 +1,166 @@
+use macroquad::prelude::*;
+
+use crate::{
+    chrome_trace::save_chrome_trace,
+    profiler::{get_counter_stats, get_frame_times, get_profile_stats},
+    utils::timestamped_path,
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+const TRACE_KEY: KeyCode = KeyCode::F4;
+const FONT_SIZE: f32 = 20.0;
+const GRAPH_WIDTH: f32 = 240.0;
+const GRAPH_HEIGHT: f32 = 80.0;
+/// Frame budget drawn as a reference line on the graph (60 fps).
+const TARGET_FRAME_MS: f32 = 1000.0 / 60.0;
+
+pub struct ProfilerOverlay {
+    pub visible: bool,
+}
+
+impl ProfilerOverlay {
+    pub const fn new() -> Self {
+        Self { visible: false }
+    }
+
+    pub fn handle_input(&mut self) {
+        if is_key_pressed(TOGGLE_KEY) {
+            self.visible = !self.visible;
+        }
+
+        if is_key_pressed(TRACE_KEY)
+            && let Err(e) = save_chrome_trace(&timestamped_path("trace", "json"))
+        {
+            log::error!("failed to write trace: {e}");
+        }
+    }
+
+    pub fn draw(&self) {
+        if !self.visible {
+            return;
+        }
+
+        let color = Color::new(0.0, 1.0, 0.0, 0.8);
+        let background = Color::new(0.0, 0.0, 0.0, 0.6);
+        let stats = get_profile_stats();
+        let counters = get_counter_stats();
+        let counter_rows = if counters.is_empty() {
+            0
+        } else {
+            counters.len() + 1
+        };
+
+        #[allow(clippy::cast_precision_loss)]
+        let height = FONT_SIZE.mul_add((stats.len() + counter_rows + 2) as f32, 8.0);
+        draw_rectangle(0.0, 0.0, 560.0, height, background);
+
+        draw_text(
+            &format!("FPS: {}  (F3 to hide)", get_fps()),
+            4.0,
+            FONT_SIZE,
+            FONT_SIZE,
+            color,
+        );
+        draw_text(
+            &format!(
+                "{:<28} {:>8} {:>8} {:>8} {:>8}",
+                "scope", "avg", "min", "max", "p95"
+            ),
+            4.0,
+            FONT_SIZE * 2.0,
+            FONT_SIZE,
+            color,
+        );
+
+        #[allow(clippy::cast_precision_loss)]
+        for (i, stat) in stats.iter().enumerate() {
+            let label = format!("{}{}", "  ".repeat(stat.depth), stat.label);
+            draw_text(
+                &format!(
+                    "{label:<28} {:>8.3} {:>8.3} {:>8.3} {:>8.3}",
+                    stat.avg.as_secs_f64() * 1000.0,
+                    stat.min.as_secs_f64() * 1000.0,
+                    stat.max.as_secs_f64() * 1000.0,
+                    stat.p95.as_secs_f64() * 1000.0,
+                ),
+                4.0,
+                FONT_SIZE.mul_add(i as f32, FONT_SIZE * 3.0),
+                FONT_SIZE,
+                color,
+            );
+        }
+
+        if !counters.is_empty() {
+            #[allow(clippy::cast_precision_loss)]
+            let top = FONT_SIZE.mul_add(stats.len() as f32, FONT_SIZE * 3.0);
+            draw_text(
+                &format!(
+                    "{:<28} {:>12} {:>12} {:>12}",
+                    "counter", "last", "avg", "max"
+                ),
+                4.0,
+                top,
+                FONT_SIZE,
+                color,
+            );
+
+            #[allow(clippy::cast_precision_loss)]
+            for (i, counter) in counters.iter().enumerate() {
+                draw_text(
+                    &format!(
+                        "{:<28} {:>12.2} {:>12.2} {:>12.2}",
+                        counter.name, counter.last, counter.avg, counter.max
+                    ),
+                    4.0,
+                    FONT_SIZE.mul_add(i as f32 + 1.0, top),
+                    FONT_SIZE,
+                    color,
+                );
+            }
+        }
+
+        Self::draw_frame_graph(color, background);
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    fn draw_frame_graph(color: Color, background: Color) {
+        let frame_times = get_frame_times();
+        let x = screen_width() - GRAPH_WIDTH - 4.0;
+        let y = 4.0;
+
+        draw_rectangle(x, y, GRAPH_WIDTH, GRAPH_HEIGHT + FONT_SIZE, background);
+
+        let max_ms = frame_times
+            .iter()
+            .map(|t| t.as_secs_f32() * 1000.0)
+            .fold(TARGET_FRAME_MS * 2.0, f32::max);
+        let bar_width = GRAPH_WIDTH / crate::profiler::PROFILER_WINDOW as f32;
+        let bottom = y + FONT_SIZE + GRAPH_HEIGHT;
+
+        for (i, time) in frame_times.iter().enumerate() {
+            let ms = time.as_secs_f32() * 1000.0;
+            let bar_height = ms / max_ms * GRAPH_HEIGHT;
+            let bar_color = if ms > TARGET_FRAME_MS { RED } else { color };
+            draw_rectangle(
+                bar_width.mul_add(i as f32, x),
+                bottom - bar_height,
+                bar_width,
+                bar_height,
+                bar_color,
+            );
+        }
+
+        let target_y = (-TARGET_FRAME_MS / max_ms).mul_add(GRAPH_HEIGHT, bottom);
+        draw_line(x, target_y, x + GRAPH_WIDTH, target_y, 1.0, YELLOW);
+
+        let last_ms = frame_times.last().map_or(0.0, |t| t.as_secs_f32() * 1000.0);
+        draw_text(
+            &format!("frame: {last_ms:6.2} ms"),
+            x + 4.0,
+            y + FONT_SIZE - 4.0,
+            FONT_SIZE * 0.8,
+            color,
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Turns the `unreachable` trap a panicking Rust cartridge ends in back into
+//! its panic message.
+//!
+//! A cartridge's panic hook usually logs the message through `console` and
+//! then aborts, which compiles to `unreachable`. The trap alone only says
+//! "unreachable", so the messages logged during the same call are searched
+//! for the hook's output.
+
+use std::fmt::Display;
+
+use wasmtime::Trap;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CartridgePanic {
+    pub message: String,
+    /// `file:line`, without the column.
+    pub location: Option<String>,
+}
+
+impl CartridgePanic {
+    /// Finds the last panic message among `messages`. Text logged after it
+    /// counts as part of the message, for hooks that log the location and
+    /// the message separately.
+    pub fn find(messages: &[String]) -> Option<Self> {
+        let (index, start) = messages
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, message)| message.find("panicked at ").map(|start| (i, start)))?;
+
+        let mut text = messages[index][start + "panicked at ".len()..].to_string();
+        for message in &messages[index + 1..] {
+            text.push('\n');
+            text.push_str(message);
+        }
+        Some(Self::parse(&text))
+    }
+
+    /// Parses what follows "panicked at ", in either the current
+    /// `file:line:col:\nmessage` form or the older `'message', file:line:col`.
+    fn parse(text: &str) -> Self {
+        if let Some(quoted) = text.strip_prefix('\'')
+            && let Some((message, location)) = quoted.rsplit_once("', ")
+        {
+            return Self {
+                message: message.to_string(),
+                location: Some(without_column(location.trim())),
+            };
+        }
+
+        let (location, message) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let location = location.strip_suffix(':').unwrap_or(location);
+        Self {
+            message: message.trim().to_string(),
+            location: (!location.is_empty()).then(|| without_column(location)),
+        }
+    }
+}
+
+fn without_column(location: &str) -> String {
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    match location.rsplit_once(':') {
+        Some((rest, column))
+            if is_number(column) && rest.rsplit_once(':').is_some_and(|(_, l)| is_number(l)) =>
+        {
+            rest.to_string()
+        }
+        _ => location.to_string(),
+    }
+}
+
+impl Display for CartridgePanic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cartridge panicked: {}", self.message)?;
+        if let Some(location) = &self.location {
+            write!(f, " at {location}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds the cartridge's panic message to `error` if it is an `unreachable`
+/// trap and the cartridge logged one during the call.
+pub fn explain_trap(error: anyhow::Error, messages: &[String]) -> anyhow::Error {
+    if error.downcast_ref::<Trap>() != Some(&Trap::UnreachableCodeReached) {
+        return error;
+    }
+    let Some(panic) = CartridgePanic::find(messages) else {
+        return error;
+    };
+
+    log::error!("{panic}");
+    error.context(panic.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(messages: &[&str]) -> Option<CartridgePanic> {
+        let messages: Vec<String> = messages.iter().map(|m| (*m).to_string()).collect();
+        CartridgePanic::find(&messages)
+    }
+
+    #[test]
+    fn current_format() {
+        let panic = find(&[
+            "loading",
+            "panicked at src/main.rs:42:9:\nindex out of bounds",
+        ]);
+        assert_eq!(
+            panic.unwrap().to_string(),
+            "cartridge panicked: index out of bounds at src/main.rs:42"
+        );
+    }
+
+    #[test]
+    fn older_format() {
+        let panic = find(&["thread 'main' panicked at 'oh no', src/lib.rs:7:5"]).unwrap();
+        assert_eq!(panic.message, "oh no");
+        assert_eq!(panic.location.as_deref(), Some("src/lib.rs:7"));
+    }
+
+    #[test]
+    fn message_logged_separately() {
+        let panic = find(&["panicked at src/game.rs:3:1:", "called `Option::unwrap()`"]);
+        assert_eq!(
+            panic.unwrap().to_string(),
+            "cartridge panicked: called `Option::unwrap()` at src/game.rs:3"
+        );
+    }
+
+    #[test]
+    fn no_panic_message() {
+        assert_eq!(find(&["hello", "world"]), None);
+    }
+}
@@ -0,0 +1,190 @@
+/// Layout of the guest framebuffer, chosen with
+/// `framebuffer::set_pixel_format`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 4 bytes per pixel, red first.
+    #[default]
+    Rgba8888,
+    /// 2 bytes per pixel, little endian, red in the top 5 bits.
+    Rgb565,
+    /// 1 byte per pixel, an index into the host-side [`Palette`].
+    Indexed8,
+}
+
+impl PixelFormat {
+    pub fn from_guest(format: u32) -> anyhow::Result<Self> {
+        match format {
+            0 => Ok(Self::Rgba8888),
+            1 => Ok(Self::Rgb565),
+            2 => Ok(Self::Indexed8),
+            _ => anyhow::bail!("unknown pixel format {format}"),
+        }
+    }
+
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgba8888 => 4,
+            Self::Rgb565 => 2,
+            Self::Indexed8 => 1,
+        }
+    }
+}
+
+pub type Palette = [[u8; 4]; 256];
+
+/// Grey ramp, so indexed cartridges show something before they set colors.
+#[allow(clippy::cast_possible_truncation)]
+pub fn default_palette() -> Box<Palette> {
+    let mut palette = Box::new([[0; 4]; 256]);
+    for (i, entry) in palette.iter_mut().enumerate() {
+        let shade = i as u8;
+        *entry = [shade, shade, shade, 255];
+    }
+    palette
+}
+
+/// Overwrites the palette entries from `first` on with `colors`, 4 bytes of
+/// RGBA each. Fails without touching the palette if they don't all fit.
+pub fn write_palette(palette: &mut Palette, first: usize, colors: &[u8]) -> anyhow::Result<()> {
+    let count = colors.len() / 4;
+    let end = first.saturating_add(count);
+    if end > palette.len() {
+        anyhow::bail!("palette entries {first}..{end} are out of range");
+    }
+    for (entry, color) in palette[first..end].iter_mut().zip(colors.chunks_exact(4)) {
+        entry.copy_from_slice(color);
+    }
+    Ok(())
+}
+
+/// Expands `src` in `format` into RGBA pixels in `dst`, which is resized to
+/// fit.
+#[allow(clippy::cast_possible_truncation)]
+pub fn convert_to_rgba(format: PixelFormat, src: &[u8], palette: &Palette, dst: &mut Vec<u8>) {
+    dst.resize(src.len() / format.bytes_per_pixel() * 4, 0);
+
+    match format {
+        PixelFormat::Rgba8888 => dst.copy_from_slice(src),
+        PixelFormat::Rgb565 => {
+            let expand = |value: u16, max: u16| {
+                ((u32::from(value) * 255 + u32::from(max) / 2) / u32::from(max)) as u8
+            };
+            for (pixel, out) in src.chunks_exact(2).zip(dst.chunks_exact_mut(4)) {
+                let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                out.copy_from_slice(&[
+                    expand(value >> 11, 31),
+                    expand((value >> 5) & 63, 63),
+                    expand(value & 31, 31),
+                    255,
+                ]);
+            }
+        }
+        PixelFormat::Indexed8 => {
+            for (&index, out) in src.iter().zip(dst.chunks_exact_mut(4)) {
+                out.copy_from_slice(&palette[usize::from(index)]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(format: PixelFormat, src: &[u8], palette: &Palette) -> Vec<u8> {
+        let mut out = Vec::new();
+        convert_to_rgba(format, src, palette, &mut out);
+        out
+    }
+
+    fn rgb565(pixels: &[u16]) -> Vec<u8> {
+        pixels.iter().flat_map(|p| p.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn rgb565_expands_to_the_full_range() {
+        let palette = default_palette();
+        let src = rgb565(&[0x0000, 0xffff, 0xf800, 0x07e0, 0x001f]);
+        assert_eq!(
+            convert(PixelFormat::Rgb565, &src, &palette),
+            [
+                0, 0, 0, 255, //
+                255, 255, 255, 255, //
+                255, 0, 0, 255, //
+                0, 255, 0, 255, //
+                0, 0, 255, 255,
+            ]
+        );
+    }
+
+    #[test]
+    fn rgb565_rounds_every_level() {
+        let palette = default_palette();
+        // 16 of 31 and 32 of 63 are just over half
+        let src = rgb565(&[16 << 11 | 32 << 5 | 1]);
+        assert_eq!(
+            convert(PixelFormat::Rgb565, &src, &palette),
+            [132, 130, 8, 255]
+        );
+
+        // every level maps to a distinct value, in order
+        for (bits, max) in [(5, 31u16), (6, 63)] {
+            let levels: Vec<u8> = (0..=max)
+                .map(|level| {
+                    let value = if bits == 5 { level << 11 } else { level << 5 };
+                    let out = convert(PixelFormat::Rgb565, &rgb565(&[value]), &palette);
+                    if bits == 5 { out[0] } else { out[1] }
+                })
+                .collect();
+            assert!(levels.windows(2).all(|pair| pair[0] < pair[1]));
+            assert_eq!((levels[0], levels[usize::from(max)]), (0, 255));
+        }
+    }
+
+    #[test]
+    fn incomplete_pixels_are_dropped() {
+        let palette = default_palette();
+        assert_eq!(
+            convert(PixelFormat::Rgb565, &[0xff, 0xff, 0xff], &palette).len(),
+            4
+        );
+        assert_eq!(
+            convert(PixelFormat::Rgba8888, &[1, 2, 3, 4], &palette),
+            [1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn indexed_pixels_look_up_the_palette() {
+        let mut palette = default_palette();
+        assert_eq!(
+            convert(PixelFormat::Indexed8, &[0, 128, 255], &palette),
+            [0, 0, 0, 255, 128, 128, 128, 255, 255, 255, 255, 255]
+        );
+
+        write_palette(&mut palette, 254, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(
+            convert(PixelFormat::Indexed8, &[255, 254, 253], &palette),
+            [5, 6, 7, 8, 1, 2, 3, 4, 253, 253, 253, 255]
+        );
+    }
+
+    #[test]
+    fn palette_writes_past_the_end_are_rejected() {
+        let mut palette = default_palette();
+        assert!(write_palette(&mut palette, 255, &[9; 8]).is_err());
+        assert!(write_palette(&mut palette, 256, &[9; 4]).is_err());
+        assert!(write_palette(&mut palette, usize::MAX, &[9; 4]).is_err());
+        assert_eq!(palette, default_palette());
+
+        write_palette(&mut palette, 256, &[]).unwrap();
+        write_palette(&mut palette, 0, &[9; 256 * 4]).unwrap();
+        assert!(palette.iter().all(|entry| *entry == [9; 4]));
+    }
+
+    #[test]
+    fn guest_formats() {
+        assert_eq!(PixelFormat::from_guest(1).unwrap(), PixelFormat::Rgb565);
+        assert!(PixelFormat::from_guest(3).is_err());
+    }
+}
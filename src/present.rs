@@ -0,0 +1,37 @@
+/// Frames the cartridge handed over with `framebuffer::present`, kept in the
+/// guest's pixel format.
+///
+/// `present` copies into the back buffer and swaps it to the front, so the
+/// host only ever reads a finished frame no matter how many `update` calls
+/// the cartridge spreads its drawing over.
+#[derive(Default)]
+pub struct PresentedFrames {
+    front: Vec<u8>,
+    back: Vec<u8>,
+    /// Set when a frame was presented since the host last displayed one.
+    fresh: bool,
+}
+
+impl PresentedFrames {
+    pub fn present(&mut self, frame: &[u8]) {
+        self.back.clear();
+        self.back.extend_from_slice(frame);
+        std::mem::swap(&mut self.front, &mut self.back);
+        self.fresh = true;
+    }
+
+    /// The most recently presented frame.
+    pub fn front(&self) -> &[u8] {
+        &self.front
+    }
+
+    /// Shows the current frame again, e.g. after the palette changed.
+    pub const fn redraw(&mut self) {
+        self.fresh = !self.front.is_empty();
+    }
+
+    /// Whether a new frame arrived since the last call.
+    pub const fn take_fresh(&mut self) -> bool {
+        std::mem::replace(&mut self.fresh, false)
+    }
+}
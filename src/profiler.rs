@@ -1,38 +1,281 @@
 use parking_lot::Mutex;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     sync::OnceLock,
     time::{Duration, Instant},
 };
 
-#[derive(Default)]
+/// How many recent frames are kept for the rolling statistics.
+pub const PROFILER_WINDOW: usize = 240;
+
+/// Category for scopes opened by the emulator itself.
+pub const CATEGORY_EMULATOR: &str = "emulator";
+/// Category for host function calls made by the cartridge.
+pub const CATEGORY_HOST_CALL: &str = "host";
+/// Category for scopes opened by the cartridge through the `profiler` module.
+pub const CATEGORY_GUEST: &str = "guest";
+
+#[derive(Clone, Debug)]
+pub struct ScopeSample {
+    pub label: String,
+    /// Index of the enclosing scope in the frame's `scopes`.
+    pub parent: Option<usize>,
+    pub category: &'static str,
+    pub depth: usize,
+    pub start: Instant,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct FrameProfile {
+    pub index: u64,
+    pub start: Instant,
+    pub duration: Duration,
+    /// Scopes in the order they were opened, so parents precede their children.
+    pub scopes: Vec<ScopeSample>,
+    pub counters: HashMap<String, f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScopeStats {
+    pub label: String,
+    pub depth: usize,
+    pub avg: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    pub p95: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct CounterStats {
+    pub name: String,
+    pub last: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+struct ScopeSamples<'a> {
+    label: &'a str,
+    depth: usize,
+    /// Index of the parent in the list of scopes seen in the window.
+    parent: Option<usize>,
+    /// Total time spent in the scope, for each frame it appeared in.
+    totals: Vec<Duration>,
+}
+
 pub struct Profiler {
-    stack: Vec<(String, Instant)>,
-    history: Vec<(String, Instant)>,
-    totals: HashMap<String, Duration>,
-    counts: HashMap<String, usize>,
+    /// Open scopes, along with their index into `current`.
+    stack: Vec<(String, Instant, usize)>,
+    current: Vec<ScopeSample>,
+    counters: HashMap<String, f64>,
+    frame_start: Option<Instant>,
+    frame_index: u64,
+    frames: VecDeque<FrameProfile>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            stack: Vec::new(),
+            current: Vec::new(),
+            counters: HashMap::new(),
+            frame_start: None,
+            frame_index: 0,
+            frames: VecDeque::with_capacity(PROFILER_WINDOW),
+        }
+    }
 }
 
 impl Profiler {
     pub fn push(&mut self, label: String) {
-        let now = Instant::now();
-        self.stack.push((label.clone(), now));
-        self.history.push((label, now));
+        self.push_with_category(label, CATEGORY_EMULATOR);
+    }
+
+    pub fn push_with_category(&mut self, label: String, category: &'static str) {
+        self.push_at(label, category, Instant::now());
+    }
+
+    fn push_at(&mut self, label: String, category: &'static str, now: Instant) {
+        self.current.push(ScopeSample {
+            label: label.clone(),
+            parent: self.stack.last().map(|&(_, _, parent)| parent),
+            category,
+            depth: self.stack.len(),
+            start: now,
+            duration: Duration::ZERO,
+        });
+        self.stack.push((label, now, self.current.len() - 1));
     }
 
     pub fn pop(&mut self) -> Option<(String, Duration)> {
-        let now = Instant::now();
+        self.pop_at(Instant::now())
+    }
+
+    fn pop_at(&mut self, now: Instant) -> Option<(String, Duration)> {
         match self.stack.pop() {
-            Some((label, start)) => {
+            Some((label, start, index)) => {
                 let elapsed = now.duration_since(start);
-                let entry = self.totals.entry(label.clone()).or_default();
-                *entry += elapsed;
-                *self.counts.entry(label.clone()).or_default() += 1;
+                self.current[index].duration = elapsed;
                 Some((label, elapsed))
             }
             None => None,
         }
     }
+
+    /// Closes the current frame and starts recording the next one. Scopes
+    /// left open are closed first so they are not carried across frames.
+    pub fn next_frame(&mut self) {
+        self.next_frame_at(Instant::now());
+    }
+
+    fn next_frame_at(&mut self, now: Instant) {
+        while self.pop_at(now).is_some() {}
+
+        if let Some(start) = self.frame_start {
+            if self.frames.len() == PROFILER_WINDOW {
+                self.frames.pop_front();
+            }
+            self.frames.push_back(FrameProfile {
+                index: self.frame_index,
+                start,
+                duration: now.duration_since(start),
+                scopes: std::mem::take(&mut self.current),
+                counters: std::mem::take(&mut self.counters),
+            });
+            self.frame_index += 1;
+        }
+
+        self.current.clear();
+        self.counters.clear();
+        self.frame_start = Some(now);
+    }
+
+    /// Adds to a counter for the current frame. Counters start every frame
+    /// at zero.
+    pub fn counter_add(&mut self, name: &str, delta: f64) {
+        *self.counters.entry(name.to_string()).or_default() += delta;
+    }
+
+    pub fn counter_set(&mut self, name: &str, value: f64) {
+        self.counters.insert(name.to_string(), value);
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = &FrameProfile> {
+        self.frames.iter()
+    }
+
+    pub fn frame_times(&self) -> Vec<Duration> {
+        self.frames.iter().map(|frame| frame.duration).collect()
+    }
+
+    /// Per-scope statistics over the rolling window, ordered as a tree:
+    /// every scope is followed by its children, and siblings are sorted by
+    /// their average time.
+    pub fn stats(&self) -> Vec<ScopeStats> {
+        // a scope is its label under a given parent, so the same label in
+        // two places is two scopes
+        let mut ids: HashMap<(Option<usize>, &str), usize> = HashMap::new();
+        let mut scopes: Vec<ScopeSamples> = Vec::new();
+
+        for frame in &self.frames {
+            let mut frame_ids = Vec::with_capacity(frame.scopes.len());
+            let mut frame_totals: HashMap<usize, Duration> = HashMap::new();
+            for scope in &frame.scopes {
+                let parent = scope.parent.map(|index| frame_ids[index]);
+                let id = *ids
+                    .entry((parent, scope.label.as_str()))
+                    .or_insert_with(|| {
+                        scopes.push(ScopeSamples {
+                            label: &scope.label,
+                            depth: scope.depth,
+                            parent,
+                            totals: Vec::new(),
+                        });
+                        scopes.len() - 1
+                    });
+                frame_ids.push(id);
+                *frame_totals.entry(id).or_default() += scope.duration;
+            }
+            for (id, total) in frame_totals {
+                scopes[id].totals.push(total);
+            }
+        }
+
+        let mut stats = Vec::with_capacity(scopes.len());
+        let mut children: HashMap<Option<usize>, Vec<usize>> = HashMap::new();
+        for (id, scope) in scopes.into_iter().enumerate() {
+            let mut samples = scope.totals;
+            samples.sort_unstable();
+            let count = samples.len();
+            let total: Duration = samples.iter().sum();
+            #[allow(clippy::cast_possible_truncation)]
+            #[allow(clippy::cast_precision_loss)]
+            #[allow(clippy::cast_sign_loss)]
+            let p95_index = ((count - 1) as f64 * 0.95).round() as usize;
+
+            #[allow(clippy::cast_possible_truncation)]
+            stats.push(ScopeStats {
+                label: scope.label.to_string(),
+                depth: scope.depth,
+                avg: total / count as u32,
+                min: samples[0],
+                max: samples[count - 1],
+                p95: samples[p95_index],
+            });
+            children.entry(scope.parent).or_default().push(id);
+        }
+
+        for siblings in children.values_mut() {
+            siblings.sort_by_key(|&id| std::cmp::Reverse(stats[id].avg));
+        }
+
+        let mut ordered = Vec::with_capacity(stats.len());
+        let mut pending: Vec<usize> = children.get(&None).cloned().unwrap_or_default();
+        pending.reverse();
+        while let Some(id) = pending.pop() {
+            if let Some(kids) = children.get(&Some(id)) {
+                pending.extend(kids.iter().rev());
+            }
+            ordered.push(stats[id].clone());
+        }
+
+        ordered
+    }
+
+    /// Per-counter statistics over the rolling window, sorted by name. Frames
+    /// in which a counter was not touched count as zero.
+    pub fn counter_stats(&self) -> Vec<CounterStats> {
+        let mut names: Vec<&str> = self
+            .frames
+            .iter()
+            .flat_map(|frame| frame.counters.keys().map(String::as_str))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        names
+            .into_iter()
+            .map(|name| {
+                let values = self
+                    .frames
+                    .iter()
+                    .map(|frame| frame.counters.get(name).copied().unwrap_or_default());
+                #[allow(clippy::cast_precision_loss)]
+                let count = self.frames.len() as f64;
+
+                CounterStats {
+                    name: name.to_string(),
+                    last: self
+                        .frames
+                        .back()
+                        .and_then(|frame| frame.counters.get(name).copied())
+                        .unwrap_or_default(),
+                    avg: values.clone().sum::<f64>() / count,
+                    max: values.fold(f64::MIN, f64::max),
+                }
+            })
+            .collect()
+    }
 }
 
 fn get_profiler() -> &'static Mutex<Profiler> {
@@ -45,6 +288,16 @@ pub fn begin_profiler(label: &str) {
     profiler.push(label.to_string());
 }
 
+pub fn begin_host_call_profiler(label: &str) {
+    let mut profiler = get_profiler().lock();
+    profiler.push_with_category(label.to_string(), CATEGORY_HOST_CALL);
+}
+
+pub fn begin_guest_profiler(label: &str) {
+    let mut profiler = get_profiler().lock();
+    profiler.push_with_category(label.to_string(), CATEGORY_GUEST);
+}
+
 pub fn end_profiler() -> Option<(String, Duration)> {
     let mut profiler = get_profiler().lock();
     profiler.pop()
@@ -56,15 +309,134 @@ pub fn rebegin_profiler(label: &str) -> Option<(String, Duration)> {
     ret
 }
 
-pub fn get_profile_averages() -> Vec<(String, Duration)> {
-    let profiler = get_profiler().lock();
-    profiler
-        .totals
-        .iter()
-        .map(|(label, &total)| {
-            let count = *profiler.counts.get(label).unwrap_or(&1);
-            #[allow(clippy::cast_possible_truncation)]
-            (label.clone(), total / count as u32)
-        })
-        .collect()
+pub fn next_profiler_frame() {
+    get_profiler().lock().next_frame();
+}
+
+pub fn profiler_counter_add(name: &str, delta: f64) {
+    get_profiler().lock().counter_add(name, delta);
+}
+
+pub fn profiler_counter_set(name: &str, value: f64) {
+    get_profiler().lock().counter_set(name, value);
+}
+
+pub fn get_profile_stats() -> Vec<ScopeStats> {
+    get_profiler().lock().stats()
+}
+
+pub fn get_counter_stats() -> Vec<CounterStats> {
+    get_profiler().lock().counter_stats()
+}
+
+pub fn get_frame_times() -> Vec<Duration> {
+    get_profiler().lock().frame_times()
+}
+
+pub fn get_profile_frames() -> Vec<FrameProfile> {
+    get_profiler().lock().frames().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn summary(stats: &[ScopeStats]) -> Vec<(&str, usize)> {
+        stats
+            .iter()
+            .map(|stat| (stat.label.as_str(), stat.depth))
+            .collect()
+    }
+
+    #[test]
+    fn stats_follow_the_scope_tree() {
+        let mut profiler = Profiler::default();
+        let mut t = Instant::now();
+        profiler.next_frame_at(t);
+
+        for frame in 0..20 {
+            let ai = frame + 1;
+            profiler.push_at("update".into(), CATEGORY_EMULATOR, t);
+            profiler.push_at("physics".into(), CATEGORY_EMULATOR, t);
+            profiler.pop_at(t + ms(5));
+            profiler.push_at("ai".into(), CATEGORY_GUEST, t + ms(5));
+            profiler.pop_at(t + ms(5 + ai));
+            profiler.pop_at(t + ms(5 + ai));
+            t += ms(5 + ai);
+
+            // entered twice, counted once with the total
+            for _ in 0..2 {
+                profiler.push_at("draw".into(), CATEGORY_EMULATOR, t);
+                profiler.pop_at(t + ms(1));
+                t += ms(1);
+            }
+            profiler.next_frame_at(t);
+        }
+
+        let stats = profiler.stats();
+        // siblings by average time, so `ai` comes before `physics`
+        assert_eq!(
+            summary(&stats),
+            [("update", 0), ("ai", 1), ("physics", 1), ("draw", 0)]
+        );
+
+        let ai = &stats[1];
+        assert_eq!(ai.min, ms(1));
+        assert_eq!(ai.max, ms(20));
+        assert_eq!(ai.p95, ms(19));
+        assert_eq!(ai.avg, Duration::from_micros(10_500));
+
+        assert_eq!(stats[0].avg, Duration::from_micros(15_500));
+        assert_eq!((stats[2].min, stats[2].max), (ms(5), ms(5)));
+        assert_eq!((stats[3].min, stats[3].max), (ms(2), ms(2)));
+    }
+
+    #[test]
+    fn window_keeps_recent_frames() {
+        let mut profiler = Profiler::default();
+        let mut t = Instant::now();
+        profiler.next_frame_at(t);
+
+        profiler.push_at("startup".into(), CATEGORY_EMULATOR, t);
+        // left open, so the end of the frame closes it
+        t += ms(3);
+        profiler.next_frame_at(t);
+        assert_eq!(profiler.stats()[0].max, ms(3));
+
+        for _ in 0..PROFILER_WINDOW {
+            profiler.push_at("tick".into(), CATEGORY_EMULATOR, t);
+            t += ms(1);
+            profiler.pop_at(t);
+            profiler.next_frame_at(t);
+        }
+
+        assert_eq!(profiler.frames().count(), PROFILER_WINDOW);
+        assert_eq!(profiler.frames().next().unwrap().index, 1);
+        assert_eq!(summary(&profiler.stats()), [("tick", 0)]);
+        assert!(profiler.frame_times().iter().all(|&time| time == ms(1)));
+    }
+
+    #[test]
+    fn slashes_in_labels_are_not_nesting() {
+        let mut profiler = Profiler::default();
+        let t = Instant::now();
+        profiler.next_frame_at(t);
+
+        profiler.push_at("ui".into(), CATEGORY_GUEST, t);
+        profiler.push_at("menu".into(), CATEGORY_GUEST, t);
+        profiler.pop_at(t + ms(4));
+        profiler.pop_at(t + ms(5));
+        profiler.push_at("ui/menu".into(), CATEGORY_GUEST, t + ms(5));
+        profiler.pop_at(t + ms(6));
+        profiler.next_frame_at(t + ms(6));
+
+        let stats = profiler.stats();
+        assert_eq!(summary(&stats), [("ui", 0), ("menu", 1), ("ui/menu", 0)]);
+        assert_eq!(stats[1].max, ms(4));
+        assert_eq!(stats[2].max, ms(1));
+    }
 }
@@ -0,0 +1,105 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use wasmtime::{Engine, GuestProfiler, Module, StoreContextMut};
+
+use crate::wasm::WASMHostState;
+
+/// How often the epoch ticker interrupts the guest to take a sample.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Samples the guest's wasm call stack while `update` runs, using wasmtime's
+/// guest profiler driven by epoch interruption.
+pub struct GuestSampler {
+    engine: Engine,
+    module: Module,
+    module_name: String,
+    profiler: Option<GuestProfiler>,
+    last_sample: Instant,
+    /// Samples are only recorded while this is set.
+    active: bool,
+}
+
+impl GuestSampler {
+    pub fn new(engine: &Engine, module: &Module, module_name: &str) -> anyhow::Result<Self> {
+        let mut sampler = Self {
+            engine: engine.clone(),
+            module: module.clone(),
+            module_name: module_name.to_string(),
+            profiler: None,
+            last_sample: Instant::now(),
+            active: false,
+        };
+        sampler.profiler = Some(sampler.new_profiler()?);
+        Ok(sampler)
+    }
+
+    fn new_profiler(&self) -> anyhow::Result<GuestProfiler> {
+        GuestProfiler::new(
+            &self.engine,
+            &self.module_name,
+            SAMPLE_INTERVAL,
+            [(self.module_name.clone(), self.module.clone())],
+        )
+    }
+
+    /// Starts or stops recording samples. The first sample after starting
+    /// only covers the time since then, not the gap between calls.
+    pub fn set_active(&mut self, active: bool) {
+        if active && !self.active {
+            self.last_sample = Instant::now();
+        }
+        self.active = active;
+    }
+
+    /// Epoch deadline handler: records one sample of the current wasm stack.
+    pub fn sample(mut store: StoreContextMut<'_, WASMHostState>) {
+        let Some(sampler) = store.data_mut().sampler.as_mut() else {
+            return;
+        };
+        if !sampler.active {
+            return;
+        }
+        let Some(mut profiler) = sampler.profiler.take() else {
+            return;
+        };
+
+        let now = Instant::now();
+        let delta = now.duration_since(sampler.last_sample);
+        sampler.last_sample = now;
+
+        profiler.sample(&store, delta);
+        store.data_mut().sampler.as_mut().unwrap().profiler = Some(profiler);
+    }
+
+    /// Writes everything sampled so far as a Firefox Profiler JSON file and
+    /// starts a fresh profile.
+    pub fn save(&mut self, path: &Path) -> anyhow::Result<()> {
+        // before swapping profilers, so a bad path doesn't lose the samples
+        let file = File::create(path)?;
+        let fresh = self.new_profiler()?;
+        if let Some(profiler) = self.profiler.replace(fresh) {
+            profiler.finish(BufWriter::new(file))?;
+            log::info!("wrote guest profile to {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Spawns the thread that bumps the engine's epoch every [`SAMPLE_INTERVAL`],
+/// which is what triggers [`GuestSampler::sample`].
+pub fn start_epoch_ticker(engine: Engine) {
+    std::thread::Builder::new()
+        .name("epoch ticker".to_string())
+        .spawn(move || {
+            loop {
+                std::thread::sleep(SAMPLE_INTERVAL);
+                engine.increment_epoch();
+            }
+        })
+        .expect("failed to spawn epoch ticker");
+}
@@ -0,0 +1,275 @@
+//! Snapshots of a running cartridge for the dev console's `save` and `load`.
+//!
+//! A snapshot holds the guest's linear memory and its exported mutable
+//! globals. Globals the cartridge does not export, like a Rust cartridge's
+//! stack pointer, can't be reached from the host; between `update` calls
+//! they are back at their starting values anyway. Host-side state such as
+//! textures, audio and storage is not included.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use wasmtime::{AsContextMut, Extern, Global, Memory, Mutability, Val};
+
+use crate::{modules::framebuffer::redraw_everything, wasm::WASMRuntime};
+
+const MAGIC: &[u8; 8] = b"GBSTATE1";
+const WASM_PAGE_SIZE: usize = 64 * 1024;
+
+/// What a save state file holds, apart from its [`MAGIC`].
+struct Snapshot {
+    /// Exported mutable globals by name.
+    globals: Vec<(String, Val)>,
+    memory: Vec<u8>,
+}
+
+impl Snapshot {
+    fn encode(&self) -> anyhow::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&u32::try_from(self.globals.len())?.to_le_bytes());
+        for (name, value) in &self.globals {
+            let (tag, bits) = match value {
+                Val::I32(v) => (0u8, u64::from(v.cast_unsigned())),
+                Val::I64(v) => (1, v.cast_unsigned()),
+                Val::F32(bits) => (2, u64::from(*bits)),
+                Val::F64(bits) => (3, *bits),
+                other => anyhow::bail!("can't save global `{name}` of type {other:?}"),
+            };
+            out.extend_from_slice(&u32::try_from(name.len())?.to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.push(tag);
+            out.extend_from_slice(&bits.to_le_bytes());
+        }
+
+        out.extend_from_slice(&(self.memory.len() as u64).to_le_bytes());
+        out.extend_from_slice(&self.memory);
+        Ok(out)
+    }
+
+    fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC) {
+            anyhow::bail!("not a save state");
+        }
+
+        let global_count = reader.u32()?;
+        let mut globals = Vec::new();
+        for _ in 0..global_count {
+            let name_len = reader.u32()? as usize;
+            let name = std::str::from_utf8(reader.take(name_len)?)?.to_string();
+            let tag = reader.take(1)?[0];
+            let bits = reader.u64()?;
+            let value = match tag {
+                0 => Val::I32(u32::try_from(bits)?.cast_signed()),
+                1 => Val::I64(bits.cast_signed()),
+                2 => Val::F32(u32::try_from(bits)?),
+                3 => Val::F64(bits),
+                _ => anyhow::bail!("global `{name}` has unknown type tag {tag}"),
+            };
+            globals.push((name, value));
+        }
+
+        let memory_len = usize::try_from(reader.u64()?)?;
+        let memory = reader.take(memory_len)?.to_vec();
+        if !reader.bytes.is_empty() {
+            anyhow::bail!(
+                "save state has {} bytes of trailing data",
+                reader.bytes.len()
+            );
+        }
+        Ok(Self { globals, memory })
+    }
+}
+
+pub fn save_state(wasm: &mut WASMRuntime, path: &Path) -> anyhow::Result<()> {
+    let store = wasm.store.get_mut();
+    let instance = wasm.instance.get_mut().unwrap();
+    let memory = wasm.memory.get_mut().unwrap();
+
+    let exported: Vec<(String, Global)> = instance
+        .exports(&mut *store)
+        .filter_map(|export| {
+            let name = export.name().to_string();
+            export.into_global().map(|global| (name, global))
+        })
+        .collect();
+    let mut globals = Vec::new();
+    for (name, global) in exported {
+        if global.ty(&*store).mutability() == Mutability::Var {
+            globals.push((name, global.get(&mut *store)));
+        }
+    }
+
+    let snapshot = Snapshot {
+        globals,
+        memory: memory.data(&*store).to_vec(),
+    };
+    fs::write(path, snapshot.encode()?)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+pub fn load_state(wasm: &mut WASMRuntime, path: &Path) -> anyhow::Result<()> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let snapshot =
+        Snapshot::decode(&bytes).with_context(|| format!("failed to load {}", path.display()))?;
+
+    let store = wasm.store.get_mut();
+    let instance = wasm.instance.get_mut().unwrap();
+    let memory = wasm.memory.get_mut().unwrap();
+
+    // check everything before changing anything
+    let mut targets = Vec::new();
+    for (name, value) in snapshot.globals {
+        let global = instance
+            .get_export(&mut *store, &name)
+            .and_then(Extern::into_global)
+            .ok_or_else(|| anyhow::anyhow!("cartridge has no global `{name}`"))?;
+        targets.push((global, value));
+    }
+
+    restore_memory(&mut *store, memory, &snapshot.memory)?;
+    for (global, value) in targets {
+        global.set(&mut *store, value)?;
+    }
+
+    redraw_everything(store.data_mut());
+    Ok(())
+}
+
+/// Copies `data` to the start of `memory`, growing it to fit. Memory can't
+/// shrink, so anything past `data` is zeroed instead.
+fn restore_memory(mut store: impl AsContextMut, memory: Memory, data: &[u8]) -> anyhow::Result<()> {
+    let current_len = memory.data_size(&store);
+    if data.len() > current_len {
+        let pages = (data.len() - current_len).div_ceil(WASM_PAGE_SIZE);
+        memory.grow(&mut store, pages as u64)?;
+    }
+    let mem = memory.data_mut(&mut store);
+    mem[..data.len()].copy_from_slice(data);
+    mem[data.len()..].fill(0);
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if len > self.bytes.len() {
+            anyhow::bail!("save state is truncated");
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::{Engine, MemoryType, Store};
+
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            globals: vec![
+                ("counter".to_string(), Val::I32(-1)),
+                ("min".to_string(), Val::I32(i32::MIN)),
+                ("ticks".to_string(), Val::I64(i64::MIN + 5)),
+                ("speed".to_string(), Val::F32(f32::NAN.to_bits() | 1)),
+                ("angle".to_string(), Val::F64((-0.5f64).to_bits())),
+            ],
+            memory: (0..=255).collect(),
+        }
+    }
+
+    /// The type tag and bits, since `Val` has no `PartialEq`.
+    fn bits(value: &Val) -> (u8, u64) {
+        match value {
+            Val::I32(v) => (0, u64::from(v.cast_unsigned())),
+            Val::I64(v) => (1, v.cast_unsigned()),
+            Val::F32(v) => (2, u64::from(*v)),
+            Val::F64(v) => (3, *v),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let saved = snapshot();
+        let loaded = Snapshot::decode(&saved.encode().unwrap()).unwrap();
+        assert_eq!(loaded.memory, saved.memory);
+        assert_eq!(loaded.globals.len(), saved.globals.len());
+        for ((name, value), (saved_name, saved_value)) in loaded.globals.iter().zip(&saved.globals)
+        {
+            assert_eq!(name, saved_name);
+            assert_eq!(bits(value), bits(saved_value), "{name}");
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let bytes = snapshot().encode().unwrap();
+        for len in 0..bytes.len() {
+            assert!(Snapshot::decode(&bytes[..len]).is_err(), "{len} bytes");
+        }
+        let mut longer = bytes;
+        longer.push(0);
+        assert!(Snapshot::decode(&longer).is_err());
+    }
+
+    #[test]
+    fn bad_headers_and_tags_are_rejected() {
+        let mut bytes = snapshot().encode().unwrap();
+        bytes[0] = b'X';
+        assert_eq!(
+            Snapshot::decode(&bytes).err().unwrap().to_string(),
+            "not a save state"
+        );
+
+        // an i32 whose bits don't fit 32 bits
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.push(b'g');
+        bytes.push(0);
+        bytes.extend_from_slice(&(1u64 << 32).to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        assert!(Snapshot::decode(&bytes).is_err());
+
+        // an unknown type
+        let tag = MAGIC.len() + 4 + 4 + 1;
+        bytes[tag] = 9;
+        assert!(Snapshot::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn restoring_grows_and_zero_fills_memory() {
+        let mut store = Store::new(&Engine::default(), ());
+        let memory = Memory::new(&mut store, MemoryType::new(1, None)).unwrap();
+        memory.data_mut(&mut store).fill(0xaa);
+
+        let data = vec![7; WASM_PAGE_SIZE * 2 + 10];
+        restore_memory(&mut store, memory, &data).unwrap();
+        assert_eq!(memory.data_size(&store), WASM_PAGE_SIZE * 3);
+        let mem = memory.data(&store);
+        assert!(mem[..data.len()].iter().all(|&b| b == 7));
+        assert!(mem[data.len()..].iter().all(|&b| b == 0));
+
+        restore_memory(&mut store, memory, &[1, 2, 3]).unwrap();
+        assert_eq!(memory.data_size(&store), WASM_PAGE_SIZE * 3);
+        let mem = memory.data(&store);
+        assert_eq!(&mem[..3], &[1, 2, 3]);
+        assert!(mem[3..].iter().all(|&b| b == 0));
+    }
+}
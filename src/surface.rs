@@ -0,0 +1,886 @@
+//! Software drawing into guest surfaces.
+//!
+//! Surfaces are tightly packed RGBA8 buffers with premultiplied alpha, and
+//! colors are passed the same way, packed little endian (red in the low
+//! byte) like `clear_surface` expects.
+
+use crate::wasm::WASMPointer;
+
+pub const BLIT_FLIP_X: u32 = 1 << 0;
+pub const BLIT_FLIP_Y: u32 = 1 << 1;
+pub const BLIT_BLEND: u32 = 1 << 2;
+
+/// Circles bigger than this are not drawn.
+pub const MAX_CIRCLE_RADIUS: u32 = 1 << 16;
+
+/// Pixels blended together by [`blend_row`]'s fast path.
+const BLEND_LANES: usize = 8;
+
+/// Size of [`BlitParams`] in guest memory.
+pub const BLIT_PARAMS_SIZE: usize = 13 * 4;
+
+pub struct Surface<'a> {
+    pub pixels: &'a mut [u8],
+    pub width: usize,
+    pub height: usize,
+}
+
+impl<'a> Surface<'a> {
+    /// Borrows a `width` x `height` surface at `ptr` out of guest memory.
+    pub fn from_guest(
+        mem: &'a mut [u8],
+        ptr: WASMPointer,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<Self> {
+        let (start, end) = guest_range(mem.len(), ptr, width, height)?;
+        Ok(Self {
+            pixels: &mut mem[start..end],
+            width: width as usize,
+            height: height as usize,
+        })
+    }
+
+    fn index(&self, x: i64, y: i64) -> Option<usize> {
+        let x = usize::try_from(x).ok()?;
+        let y = usize::try_from(y).ok()?;
+        (x < self.width && y < self.height).then(|| (y * self.width + x) * 4)
+    }
+
+    fn plot(&mut self, x: i64, y: i64, color: [u8; 4], blend: bool) {
+        if let Some(i) = self.index(x, y) {
+            blend_pixel(&mut self.pixels[i..i + 4], color, blend);
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
+    #[allow(clippy::cast_sign_loss)]
+    pub fn fill_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: u32, blend: bool) {
+        let color = color.to_le_bytes();
+        let left = i64::from(x).clamp(0, self.width as i64) as usize;
+        let top = i64::from(y).clamp(0, self.height as i64) as usize;
+        let right = (i64::from(x) + i64::from(w)).clamp(0, self.width as i64) as usize;
+        let bottom = (i64::from(y) + i64::from(h)).clamp(0, self.height as i64) as usize;
+
+        for row in top..bottom {
+            let start = (row * self.width + left) * 4;
+            let end = (row * self.width + right) * 4;
+            for pixel in self.pixels[start..end].chunks_exact_mut(4) {
+                blend_pixel(pixel, color, blend);
+            }
+        }
+    }
+
+    /// Draws `src` with its top left corner at `dest_x`, `dest_y`, clipped on
+    /// all four sides. Fully transparent source pixels are skipped.
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
+    #[allow(clippy::cast_sign_loss)]
+    pub fn blit(
+        &mut self,
+        src: &[u8],
+        src_w: usize,
+        src_h: usize,
+        dest_x: i32,
+        dest_y: i32,
+        blend: bool,
+    ) {
+        let (dest_x, dest_y) = (i64::from(dest_x), i64::from(dest_y));
+        let left = dest_x.max(0);
+        let top = dest_y.max(0);
+        let right = (dest_x + src_w as i64).min(self.width as i64);
+        let bottom = (dest_y + src_h as i64).min(self.height as i64);
+        if left >= right || top >= bottom {
+            return;
+        }
+
+        let src_x = (left - dest_x) as usize;
+        let src_y = (top - dest_y) as usize;
+        let (left, top) = (left as usize, top as usize);
+        let row_len = (right as usize - left) * 4;
+
+        for row in 0..(bottom as usize - top) {
+            let d = ((top + row) * self.width + left) * 4;
+            let s = ((src_y + row) * src_w + src_x) * 4;
+            blend_row(
+                &mut self.pixels[d..d + row_len],
+                &src[s..s + row_len],
+                blend,
+            );
+        }
+    }
+
+    /// Bresenham line including both end points. The line is clipped to the
+    /// surface first, so the loop never runs past its edges.
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32, blend: bool) {
+        let color = color.to_le_bytes();
+        let from = (i64::from(x0), i64::from(y0));
+        let to = (i64::from(x1), i64::from(y1));
+        let Some(((mut x, mut y), (x1, y1))) = self.clip_line(from, to) else {
+            return;
+        };
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let step_x = if x < x1 { 1 } else { -1 };
+        let step_y = if y < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        loop {
+            self.plot(x, y, color, blend);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += step_x;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Liang-Barsky clip of the segment to the surface plus a pixel of
+    /// margin, so rounding the new end points can't lose a pixel on the edge.
+    /// End points already inside are kept exactly.
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    fn clip_line(&self, from: (i64, i64), to: (i64, i64)) -> Option<((i64, i64), (i64, i64))> {
+        let (x0, y0) = (from.0 as f64, from.1 as f64);
+        let (dx, dy) = ((to.0 - from.0) as f64, (to.1 - from.1) as f64);
+        let (mut enter, mut exit) = (0.0f64, 1.0f64);
+        // each edge as `p * t <= q`
+        let edges = [
+            (-dx, x0 + 1.0),
+            (dx, self.width as f64 - x0),
+            (-dy, y0 + 1.0),
+            (dy, self.height as f64 - y0),
+        ];
+        for (p, q) in edges {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else if p < 0.0 {
+                enter = enter.max(q / p);
+            } else {
+                exit = exit.min(q / p);
+            }
+        }
+        if enter > exit {
+            return None;
+        }
+
+        let at = |t: f64| {
+            (
+                t.mul_add(dx, x0).round() as i64,
+                t.mul_add(dy, y0).round() as i64,
+            )
+        };
+        Some((at(enter), at(exit)))
+    }
+
+    /// Midpoint circle, outlined or filled. Each pixel is drawn exactly once
+    /// so blending does not double up where octants meet.
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
+    #[allow(clippy::cast_sign_loss)]
+    pub fn draw_circle(
+        &mut self,
+        cx: i32,
+        cy: i32,
+        radius: u32,
+        color: u32,
+        filled: bool,
+        blend: bool,
+    ) {
+        if radius > MAX_CIRCLE_RADIUS {
+            return;
+        }
+        let (cx, cy) = (i64::from(cx), i64::from(cy));
+        let radius = i64::from(radius);
+        let rgba = color.to_le_bytes();
+
+        // half-width of the circle on each row, from the top down to the middle
+        let mut spans = vec![0i64; radius as usize + 1];
+        let (mut x, mut y) = (radius, 0i64);
+        let mut err = 1 - radius;
+        while x >= y {
+            spans[y as usize] = spans[y as usize].max(x);
+            spans[x as usize] = spans[x as usize].max(y);
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
+            }
+        }
+
+        for (dy, &half) in (0i64..).zip(&spans) {
+            let rows: &[i64] = if dy == 0 { &[0] } else { &[-dy, dy] };
+            for &row in rows {
+                if filled {
+                    let left = (cx - half).max(0);
+                    let right = (cx + half).min(self.width as i64 - 1);
+                    for px in left..=right {
+                        self.plot(px, cy + row, rgba, blend);
+                    }
+                    continue;
+                }
+
+                // the outline of this row runs from the next row's span out
+                // to this one, so steep parts of the circle stay connected
+                let inner = spans
+                    .get(dy as usize + 1)
+                    .map_or(0, |&next| (next + 1).min(half));
+                for px in inner..=half {
+                    self.plot(cx - px, cy + row, rgba, blend);
+                    if px != 0 {
+                        self.plot(cx + px, cy + row, rgba, blend);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draws `src` transformed by `params`, sampling the nearest source pixel
+    /// for every destination pixel the transformed rect covers.
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::cast_sign_loss)]
+    pub fn blit_ex(&mut self, src: &[u8], src_w: usize, src_h: usize, params: &BlitParams) {
+        let Some((sub_x, sub_y, sub_w, sub_h)) = params.source_rect(src_w, src_h) else {
+            return;
+        };
+        if params.scale_x == 0.0 || params.scale_y == 0.0 {
+            return;
+        }
+
+        let (sin, cos) = params.rotation.sin_cos();
+        let to_dest = |x: f32, y: f32| {
+            let x = (x - params.origin_x) * params.scale_x;
+            let y = (y - params.origin_y) * params.scale_y;
+            (
+                x.mul_add(cos, -y * sin) + params.dest_x,
+                x.mul_add(sin, y * cos) + params.dest_y,
+            )
+        };
+
+        let corners = [
+            to_dest(0.0, 0.0),
+            to_dest(sub_w as f32, 0.0),
+            to_dest(0.0, sub_h as f32),
+            to_dest(sub_w as f32, sub_h as f32),
+        ];
+        let min_x = corners.iter().map(|c| c.0).fold(f32::INFINITY, f32::min);
+        let max_x = corners
+            .iter()
+            .map(|c| c.0)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let min_y = corners.iter().map(|c| c.1).fold(f32::INFINITY, f32::min);
+        let max_y = corners
+            .iter()
+            .map(|c| c.1)
+            .fold(f32::NEG_INFINITY, f32::max);
+
+        let left = min_x.floor().max(0.0) as usize;
+        let top = min_y.floor().max(0.0) as usize;
+        let right = (max_x.ceil().max(0.0) as usize).min(self.width);
+        let bottom = (max_y.ceil().max(0.0) as usize).min(self.height);
+
+        let blend = params.flags & BLIT_BLEND != 0;
+        let tint = params.tint.to_le_bytes();
+
+        for y in top..bottom {
+            for x in left..right {
+                // map the destination pixel center back into the source rect
+                let dx = x as f32 + 0.5 - params.dest_x;
+                let dy = y as f32 + 0.5 - params.dest_y;
+                let u = dx.mul_add(cos, dy * sin) / params.scale_x + params.origin_x;
+                let v = dy.mul_add(cos, -dx * sin) / params.scale_y + params.origin_y;
+                if u < 0.0 || v < 0.0 || u >= sub_w as f32 || v >= sub_h as f32 {
+                    continue;
+                }
+
+                let mut u = u as usize;
+                let mut v = v as usize;
+                if params.flags & BLIT_FLIP_X != 0 {
+                    u = sub_w - 1 - u;
+                }
+                if params.flags & BLIT_FLIP_Y != 0 {
+                    v = sub_h - 1 - v;
+                }
+
+                let sidx = ((sub_y + v) * src_w + sub_x + u) * 4;
+                let color = tinted(&src[sidx..sidx + 4], tint);
+                if color[3] == 0 {
+                    continue;
+                }
+
+                let didx = (y * self.width + x) * 4;
+                blend_pixel(&mut self.pixels[didx..didx + 4], color, blend);
+            }
+        }
+    }
+}
+
+/// Transform for [`Surface::blit_ex`], laid out in guest memory as 13
+/// little endian 32 bit fields in this order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlitParams {
+    /// Source sub-rectangle. A zero width or height means the whole source.
+    pub src_x: u32,
+    pub src_y: u32,
+    pub src_w: u32,
+    pub src_h: u32,
+    /// Where the origin ends up in the destination.
+    pub dest_x: f32,
+    pub dest_y: f32,
+    pub scale_x: f32,
+    pub scale_y: f32,
+    /// Clockwise, in radians, around the origin.
+    pub rotation: f32,
+    /// Pivot for scaling and rotation, relative to the source sub-rectangle.
+    pub origin_x: f32,
+    pub origin_y: f32,
+    /// Premultiplied color every source pixel is multiplied by.
+    pub tint: u32,
+    /// [`BLIT_FLIP_X`], [`BLIT_FLIP_Y`] and [`BLIT_BLEND`].
+    pub flags: u32,
+}
+
+impl BlitParams {
+    pub fn read(mem: &[u8], ptr: WASMPointer) -> anyhow::Result<Self> {
+        let start = ptr as usize;
+        let bytes = mem
+            .get(start..start + BLIT_PARAMS_SIZE)
+            .ok_or_else(|| anyhow::anyhow!("blit params are out of bounds"))?;
+        let word = |i: usize| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+
+        Ok(Self {
+            src_x: word(0),
+            src_y: word(1),
+            src_w: word(2),
+            src_h: word(3),
+            dest_x: f32::from_bits(word(4)),
+            dest_y: f32::from_bits(word(5)),
+            scale_x: f32::from_bits(word(6)),
+            scale_y: f32::from_bits(word(7)),
+            rotation: f32::from_bits(word(8)),
+            origin_x: f32::from_bits(word(9)),
+            origin_y: f32::from_bits(word(10)),
+            tint: word(11),
+            flags: word(12),
+        })
+    }
+
+    /// The source sub-rectangle clipped to the source, or `None` if empty.
+    fn source_rect(&self, src_w: usize, src_h: usize) -> Option<(usize, usize, usize, usize)> {
+        let x = (self.src_x as usize).min(src_w);
+        let y = (self.src_y as usize).min(src_h);
+        let w = if self.src_w == 0 {
+            src_w
+        } else {
+            self.src_w as usize
+        };
+        let h = if self.src_h == 0 {
+            src_h
+        } else {
+            self.src_h as usize
+        };
+        let w = w.min(src_w - x);
+        let h = h.min(src_h - y);
+        (w > 0 && h > 0).then_some((x, y, w, h))
+    }
+}
+
+/// Byte range of a `width` x `height` surface at `ptr`, checked against the
+/// size of guest memory.
+pub fn guest_range(
+    mem_len: usize,
+    ptr: WASMPointer,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<(usize, usize)> {
+    let start = ptr as usize;
+    let end = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .and_then(|len| len.checked_add(start))
+        .filter(|&end| end <= mem_len)
+        .ok_or_else(|| anyhow::anyhow!("surface {width}x{height} at {ptr:#x} is out of bounds"))?;
+    Ok((start, end))
+}
+
+/// Multiplies a premultiplied pixel by a premultiplied tint.
+#[allow(clippy::cast_possible_truncation)]
+pub fn tinted(src: &[u8], tint: [u8; 4]) -> [u8; 4] {
+    let mul = |s: u8, t: u8| ((u32::from(s) * u32::from(t) + 127) / 255) as u8;
+    [
+        mul(src[0], tint[0]),
+        mul(src[1], tint[1]),
+        mul(src[2], tint[2]),
+        mul(src[3], tint[3]),
+    ]
+}
+
+/// Writes `src` over `dst`, compositing premultiplied "source over" when
+/// `blend` is set. Same rounding as `blit_premultiplied_clipped`.
+#[allow(clippy::cast_possible_truncation)]
+pub fn blend_pixel(dst: &mut [u8], src: [u8; 4], blend: bool) {
+    let sa = src[3];
+    if !blend || sa == 255 {
+        dst.copy_from_slice(&src);
+        return;
+    }
+    if sa == 0 {
+        return;
+    }
+
+    let inv = 255 - u32::from(sa);
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = (u32::from(s) + (u32::from(*d) * inv + 127) / 255).min(255) as u8;
+    }
+}
+
+/// [`blend_pixel`] for blits, which leave the destination alone wherever the
+/// source is fully transparent, even without blending.
+pub fn blit_pixel(dst: &mut [u8], src: [u8; 4], blend: bool) {
+    if src[3] != 0 {
+        blend_pixel(dst, src, blend);
+    }
+}
+
+/// Blits one row of pixels, [`BLEND_LANES`] at a time. Gives exactly the
+/// same result as calling [`blit_pixel`] for each pixel.
+pub fn blend_row(dst: &mut [u8], src: &[u8], blend: bool) {
+    let mut dst_chunks = dst.chunks_exact_mut(BLEND_LANES * 4);
+    let mut src_chunks = src.chunks_exact(BLEND_LANES * 4);
+    for (d, s) in (&mut dst_chunks).zip(&mut src_chunks) {
+        blend_chunk(d.try_into().unwrap(), s.try_into().unwrap(), blend);
+    }
+
+    let dst_rest = dst_chunks.into_remainder().chunks_exact_mut(4);
+    for (d, s) in dst_rest.zip(src_chunks.remainder().chunks_exact(4)) {
+        blit_pixel(d, s.try_into().unwrap(), blend);
+    }
+}
+
+/// Branch-free over the lanes so the compiler can vectorise it. Dividing by
+/// 255 is replaced with shifts, which rounds the same for every input.
+#[allow(clippy::cast_possible_truncation)]
+fn blend_chunk(dst: &mut [u8; BLEND_LANES * 4], src: &[u8; BLEND_LANES * 4], blend: bool) {
+    let alphas = src.iter().skip(3).step_by(4);
+    if alphas.clone().all(|&a| a == 0) {
+        return;
+    }
+    if alphas.clone().all(|&a| a == 255) || (!blend && alphas.clone().all(|&a| a != 0)) {
+        dst.copy_from_slice(src);
+        return;
+    }
+
+    let mut out = [0u8; BLEND_LANES * 4];
+    for (i, out) in out.iter_mut().enumerate() {
+        let sa = src[i | 3];
+        let x = u16::from(dst[i]) * (255 - u16::from(sa)) + 128;
+        let blended = (u16::from(src[i]) + ((x + (x >> 8)) >> 8)).min(255) as u8;
+
+        *out = if sa == 0 {
+            dst[i]
+        } else if !blend || sa == 255 {
+            src[i]
+        } else {
+            blended
+        };
+    }
+    *dst = out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift64, so the property tests are reproducible without extra
+    /// dependencies.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        fn below(&mut self, n: u64) -> usize {
+            (self.next() % n) as usize
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        fn range(&mut self, min: i32, max: i32) -> i32 {
+            min + (self.next() % u64::from((max - min).cast_unsigned() + 1)) as i32
+        }
+
+        /// Random premultiplied pixels, with plenty of the fully transparent
+        /// and fully opaque alphas that take the shortcuts.
+        #[allow(clippy::cast_possible_truncation)]
+        fn pixels(&mut self, count: usize) -> Vec<u8> {
+            let mut out = Vec::with_capacity(count * 4);
+            for _ in 0..count {
+                let alpha = match self.below(4) {
+                    0 => 0,
+                    1 => 255,
+                    _ => self.next() as u8,
+                };
+                for _ in 0..3 {
+                    out.push((self.below(u64::from(alpha) + 1)) as u8);
+                }
+                out.push(alpha);
+            }
+            out
+        }
+    }
+
+    fn reference_row(dst: &mut [u8], src: &[u8], blend: bool) {
+        for (d, s) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+            blit_pixel(d, s.try_into().unwrap(), blend);
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
+    #[allow(clippy::cast_sign_loss)]
+    fn reference_blit(
+        dst: &mut Surface,
+        src: &[u8],
+        src_w: usize,
+        src_h: usize,
+        dest_x: i32,
+        dest_y: i32,
+        blend: bool,
+    ) {
+        for sy in 0..src_h {
+            for sx in 0..src_w {
+                let x = i64::from(dest_x) + sx as i64;
+                let y = i64::from(dest_y) + sy as i64;
+                if x < 0 || y < 0 || x >= dst.width as i64 || y >= dst.height as i64 {
+                    continue;
+                }
+                let d = (y as usize * dst.width + x as usize) * 4;
+                let s = (sy * src_w + sx) * 4;
+                blit_pixel(
+                    &mut dst.pixels[d..d + 4],
+                    src[s..s + 4].try_into().unwrap(),
+                    blend,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shifted_division_rounds_like_scalar() {
+        for d in 0..=255u16 {
+            for inv in 0..=255u16 {
+                let x = d * inv + 128;
+                assert_eq!((x + (x >> 8)) >> 8, (d * inv + 127) / 255);
+            }
+        }
+    }
+
+    #[test]
+    fn blend_row_matches_reference() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..2000 {
+            let len = rng.below(40);
+            let blend = rng.below(2) == 0;
+            let src = rng.pixels(len);
+            let mut fast = rng.pixels(len);
+            let mut expected = fast.clone();
+
+            blend_row(&mut fast, &src, blend);
+            reference_row(&mut expected, &src, blend);
+            assert_eq!(fast, expected, "len {len}, blend {blend}");
+        }
+    }
+
+    #[test]
+    fn blit_matches_reference_with_any_offset() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..1000 {
+            let (dst_w, dst_h) = (rng.below(24) + 1, rng.below(24) + 1);
+            let (src_w, src_h) = (rng.below(24) + 1, rng.below(24) + 1);
+            let dest_x = rng.range(-30, 30);
+            let dest_y = rng.range(-30, 30);
+            let blend = rng.below(2) == 0;
+            let src = rng.pixels(src_w * src_h);
+            let mut fast = rng.pixels(dst_w * dst_h);
+            let mut expected = fast.clone();
+
+            Surface {
+                pixels: &mut fast,
+                width: dst_w,
+                height: dst_h,
+            }
+            .blit(&src, src_w, src_h, dest_x, dest_y, blend);
+            reference_blit(
+                &mut Surface {
+                    pixels: &mut expected,
+                    width: dst_w,
+                    height: dst_h,
+                },
+                &src,
+                src_w,
+                src_h,
+                dest_x,
+                dest_y,
+                blend,
+            );
+            assert_eq!(
+                fast, expected,
+                "{src_w}x{src_h} at {dest_x},{dest_y} onto {dst_w}x{dst_h}"
+            );
+        }
+    }
+
+    #[test]
+    fn blit_off_every_edge_is_a_no_op() {
+        let src = vec![255; 4 * 4 * 4];
+        let mut pixels = vec![0; 4 * 4 * 4];
+        for (x, y) in [(-4, 0), (4, 0), (0, -4), (0, 4), (i32::MIN, i32::MAX)] {
+            Surface {
+                pixels: &mut pixels,
+                width: 4,
+                height: 4,
+            }
+            .blit(&src, 4, 4, x, y, true);
+        }
+        assert!(pixels.iter().all(|&p| p == 0));
+    }
+
+    const RED: u32 = 0xff00_00ff;
+    /// Red at half alpha, premultiplied.
+    const HALF_RED: u32 = 0x8000_0080;
+
+    fn lit(pixels: &[u8], width: usize) -> Vec<(usize, usize)> {
+        (0..pixels.len() / 4)
+            .filter(|i| pixels[i * 4 + 3] != 0)
+            .map(|i| (i % width, i / width))
+            .collect()
+    }
+
+    fn canvas(pixels: &mut [u8], width: usize) -> Surface<'_> {
+        let height = pixels.len() / 4 / width;
+        Surface {
+            pixels,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn fill_rect_clips_and_skips_degenerate_rects() {
+        let mut pixels = vec![0; 4 * 4 * 4];
+        let mut surface = canvas(&mut pixels, 4);
+        surface.fill_rect(0, 0, 0, 4, RED, false);
+        surface.fill_rect(0, 0, 4, 0, RED, false);
+        surface.fill_rect(4, 0, 4, 4, RED, false);
+        surface.fill_rect(i32::MIN, i32::MIN, 4, 4, RED, false);
+        assert!(lit(&pixels, 4).is_empty());
+
+        canvas(&mut pixels, 4).fill_rect(-2, 3, 4, u32::MAX, RED, false);
+        assert_eq!(lit(&pixels, 4), [(0, 3), (1, 3)]);
+
+        canvas(&mut pixels, 4).fill_rect(i32::MIN, i32::MIN, u32::MAX, u32::MAX, RED, false);
+        assert_eq!(lit(&pixels, 4).len(), 16);
+    }
+
+    #[test]
+    fn line_includes_both_end_points() {
+        let mut pixels = vec![0; 8 * 8 * 4];
+        canvas(&mut pixels, 8).draw_line(1, 1, 4, 2, RED, false);
+        assert_eq!(lit(&pixels, 8), [(1, 1), (2, 1), (3, 2), (4, 2)]);
+
+        let mut pixels = vec![0; 8 * 8 * 4];
+        canvas(&mut pixels, 8).draw_line(5, 6, 5, 6, RED, false);
+        assert_eq!(lit(&pixels, 8), [(5, 6)]);
+    }
+
+    #[test]
+    fn huge_lines_are_clipped_before_drawing() {
+        let mut pixels = vec![0; 16 * 16 * 4];
+        let mut surface = canvas(&mut pixels, 16);
+        surface.draw_line(i32::MIN, 3, i32::MAX, 3, RED, false);
+        surface.draw_line(5, i32::MAX, 5, i32::MIN, RED, false);
+        let cross = lit(&pixels, 16);
+        assert_eq!(cross.len(), 31);
+        assert!(cross.iter().all(|&(x, y)| x == 5 || y == 3));
+
+        let mut pixels = vec![0; 16 * 16 * 4];
+        canvas(&mut pixels, 16).draw_line(-100, -100, 100, 100, RED, false);
+        assert_eq!(
+            lit(&pixels, 16),
+            (0..16).map(|i| (i, i)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn lines_outside_draw_nothing() {
+        let mut pixels = vec![0; 4 * 4 * 4];
+        let mut surface = canvas(&mut pixels, 4);
+        surface.draw_line(-5, -1, 10, -1, RED, false);
+        surface.draw_line(4, i32::MIN, 4, i32::MAX, RED, false);
+        surface.draw_line(i32::MIN, i32::MAX, i32::MIN, i32::MIN, RED, false);
+        surface.draw_line(-10, 2, 2, -10, RED, false);
+        assert!(lit(&pixels, 4).is_empty());
+    }
+
+    #[test]
+    fn circles_are_symmetric() {
+        for filled in [false, true] {
+            for radius in 0..12 {
+                let mut pixels = vec![0; 25 * 25 * 4];
+                canvas(&mut pixels, 25).draw_circle(12, 12, radius, RED, filled, false);
+                let circle = lit(&pixels, 25);
+                assert!(!circle.is_empty());
+                for &(x, y) in &circle {
+                    for mirrored in [(24 - x, y), (x, 24 - y), (y, x)] {
+                        assert!(
+                            circle.contains(&mirrored),
+                            "radius {radius}, filled {filled}: {:?} without {mirrored:?}",
+                            (x, y)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn circles_blend_each_pixel_once() {
+        for filled in [false, true] {
+            for radius in 0..12 {
+                let mut pixels = vec![0; 25 * 25 * 4];
+                canvas(&mut pixels, 25).draw_circle(12, 12, radius, HALF_RED, filled, true);
+                for pixel in pixels.chunks_exact(4).filter(|p| p[3] != 0) {
+                    assert_eq!(pixel, HALF_RED.to_le_bytes(), "radius {radius}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn circles_off_the_surface_or_too_big_are_skipped() {
+        let mut pixels = vec![0; 4 * 4 * 4];
+        let mut surface = canvas(&mut pixels, 4);
+        surface.draw_circle(i32::MIN, i32::MIN, 3, RED, true, false);
+        surface.draw_circle(2, 2, MAX_CIRCLE_RADIUS + 1, RED, true, false);
+        surface.draw_circle(-5, 2, 4, RED, false, false);
+        assert!(lit(&pixels, 4).is_empty());
+    }
+
+    fn identity() -> BlitParams {
+        BlitParams {
+            src_x: 0,
+            src_y: 0,
+            src_w: 0,
+            src_h: 0,
+            dest_x: 0.0,
+            dest_y: 0.0,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            rotation: 0.0,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            tint: 0xffff_ffff,
+            flags: 0,
+        }
+    }
+
+    /// A 2x2 source with a different opaque color in each corner.
+    const CORNERS: [u8; 16] = [
+        10, 0, 0, 255, 20, 0, 0, 255, //
+        30, 0, 0, 255, 40, 0, 0, 255,
+    ];
+
+    fn blit_ex_reds(params: &BlitParams) -> Vec<u8> {
+        let mut pixels = vec![0; 2 * 2 * 4];
+        canvas(&mut pixels, 2).blit_ex(&CORNERS, 2, 2, params);
+        pixels.chunks_exact(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn blit_ex_flips() {
+        assert_eq!(blit_ex_reds(&identity()), [10, 20, 30, 40]);
+        let flip = |flags| BlitParams {
+            flags,
+            ..identity()
+        };
+        assert_eq!(blit_ex_reds(&flip(BLIT_FLIP_X)), [20, 10, 40, 30]);
+        assert_eq!(blit_ex_reds(&flip(BLIT_FLIP_Y)), [30, 40, 10, 20]);
+        assert_eq!(
+            blit_ex_reds(&flip(BLIT_FLIP_X | BLIT_FLIP_Y)),
+            [40, 30, 20, 10]
+        );
+    }
+
+    #[test]
+    fn blit_ex_tints_and_skips_degenerate_params() {
+        let mut pixels = vec![0; 4];
+        let src = [200, 100, 50, 255];
+        let params = BlitParams {
+            tint: 0x8080_8080,
+            ..identity()
+        };
+        canvas(&mut pixels, 1).blit_ex(&src, 1, 1, &params);
+        assert_eq!(pixels, [100, 50, 25, 128]);
+
+        let mut pixels = vec![0; 2 * 2 * 4];
+        let mut surface = canvas(&mut pixels, 2);
+        for params in [
+            BlitParams {
+                scale_x: 0.0,
+                ..identity()
+            },
+            BlitParams {
+                src_x: 2,
+                ..identity()
+            },
+            BlitParams {
+                tint: 0,
+                ..identity()
+            },
+            BlitParams {
+                dest_x: -2.0,
+                ..identity()
+            },
+        ] {
+            surface.blit_ex(&CORNERS, 2, 2, &params);
+        }
+        assert!(lit(&pixels, 2).is_empty());
+    }
+
+    #[test]
+    fn source_rect_defaults_and_clips() {
+        let rect = |src_x, src_y, src_w, src_h| {
+            BlitParams {
+                src_x,
+                src_y,
+                src_w,
+                src_h,
+                ..identity()
+            }
+            .source_rect(8, 4)
+        };
+        assert_eq!(rect(0, 0, 0, 0), Some((0, 0, 8, 4)));
+        assert_eq!(rect(2, 1, 3, 2), Some((2, 1, 3, 2)));
+        assert_eq!(rect(6, 3, 10, 10), Some((6, 3, 2, 1)));
+        assert_eq!(rect(6, 0, 0, 0), Some((6, 0, 2, 4)));
+        assert_eq!(rect(8, 0, 1, 1), None);
+        assert_eq!(rect(u32::MAX, u32::MAX, u32::MAX, u32::MAX), None);
+    }
+}
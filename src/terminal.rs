@@ -0,0 +1,486 @@
+//! Renders the framebuffer to an ANSI terminal with half-block characters
+//! and 24-bit color, for running the emulator over SSH.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write as _,
+    io::Write as _,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    audio_manager::get_raw_audio_manager,
+    filters::FilterPipeline,
+    font::{QueuedText, get_font_registry},
+    host_calls::next_host_call_frame,
+    input_source::{InputEvent, InputSource},
+    keyboard::{KeyEvent, KeyEventKind, MOD_SHIFT},
+    profiler::{begin_profiler, end_profiler, next_profiler_frame, rebegin_profiler},
+    surface::Surface,
+    wasm::WASMRuntime,
+};
+
+/// `update` still runs at 60 Hz; only drawing is throttled.
+const UPDATE_INTERVAL: Duration = Duration::from_micros(16_667);
+/// Draw every 4th frame, so 15 fps.
+const RENDER_EVERY: u64 = 4;
+
+/// Terminals only report key presses, so a key counts as held for a while
+/// after each one. The first press has to outlast the terminal's autorepeat
+/// delay; repeats after that come in quickly.
+const FIRST_PRESS_HOLD: Duration = Duration::from_millis(500);
+const REPEAT_HOLD: Duration = Duration::from_millis(100);
+
+/// LWJGL key codes, as used by [`crate::utils::map_key`].
+const KEY_ESCAPE: i32 = 256;
+const KEY_ENTER: i32 = 257;
+const KEY_TAB: i32 = 258;
+const KEY_BACKSPACE: i32 = 259;
+const KEY_RIGHT: i32 = 262;
+const KEY_LEFT: i32 = 263;
+const KEY_DOWN: i32 = 264;
+const KEY_UP: i32 = 265;
+const KEY_LEFT_SHIFT: i32 = 340;
+
+/// Keyboard state built from what the TTY sends.
+#[derive(Default)]
+pub struct TerminalInput {
+    held_until: HashMap<i32, Instant>,
+    chars: VecDeque<char>,
+    /// Presses and repeats since the last poll.
+    events: Vec<KeyEvent>,
+    /// Set when Ctrl+C is pressed, since raw mode swallows the signal.
+    pub quit: bool,
+}
+
+impl TerminalInput {
+    pub fn is_key_down(&self, key: i32) -> bool {
+        self.held_until
+            .get(&key)
+            .is_some_and(|&until| Instant::now() < until)
+    }
+
+    pub fn pop_char(&mut self) -> Option<char> {
+        self.chars.pop_front()
+    }
+
+    fn press(&mut self, key: i32, mods: u32) {
+        let now = Instant::now();
+        let (hold, kind) = if self.is_key_down(key) {
+            (REPEAT_HOLD, KeyEventKind::Repeat)
+        } else {
+            (FIRST_PRESS_HOLD, KeyEventKind::Press)
+        };
+        self.held_until.insert(key, now + hold);
+        self.events.push(KeyEvent::from_lwjgl(kind, key, mods));
+    }
+
+    /// Moves key events and characters into `events`, releasing keys whose
+    /// hold ran out.
+    fn drain_events(&mut self, events: &mut Vec<InputEvent>) {
+        events.extend(self.events.drain(..).map(InputEvent::Key));
+        while let Some(ch) = self.pop_char() {
+            events.push(InputEvent::Char(ch));
+        }
+
+        let now = Instant::now();
+        self.held_until.retain(|&key, &mut until| {
+            let held = now < until;
+            if !held {
+                events.push(InputEvent::Key(KeyEvent::from_lwjgl(
+                    KeyEventKind::Release,
+                    key,
+                    0,
+                )));
+            }
+            held
+        });
+    }
+
+    /// Parses raw bytes read from the TTY.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let mut i = 0;
+        while i < bytes.len() {
+            let byte = bytes[i];
+            i += 1;
+
+            match byte {
+                0x03 => self.quit = true,
+                0x1b => {
+                    // CSI or SS3 sequence, otherwise a lone escape
+                    if let Some(b'[' | b'O') = bytes.get(i).copied() {
+                        let start = i + 1;
+                        let end = bytes[start..]
+                            .iter()
+                            .position(|b| (0x40..=0x7e).contains(b))
+                            .map_or(bytes.len(), |p| start + p);
+                        match bytes.get(end) {
+                            Some(b'A') => self.press(KEY_UP, 0),
+                            Some(b'B') => self.press(KEY_DOWN, 0),
+                            Some(b'C') => self.press(KEY_RIGHT, 0),
+                            Some(b'D') => self.press(KEY_LEFT, 0),
+                            _ => {}
+                        }
+                        i = end + 1;
+                    } else {
+                        self.press(KEY_ESCAPE, 0);
+                    }
+                }
+                b'\r' | b'\n' => self.press(KEY_ENTER, 0),
+                b'\t' => self.press(KEY_TAB, 0),
+                0x08 | 0x7f => self.press(KEY_BACKSPACE, 0),
+                b' '..=b'~' => {
+                    if let Some(key) = key_for_char(byte) {
+                        let mods = if byte.is_ascii_uppercase() {
+                            self.press(KEY_LEFT_SHIFT, MOD_SHIFT);
+                            MOD_SHIFT
+                        } else {
+                            0
+                        };
+                        self.press(key, mods);
+                    }
+                    self.chars.push_back(char::from(byte));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl InputSource for TerminalInput {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) {
+        self.feed(&read_stdin());
+        self.drain_events(events);
+    }
+
+    fn finished(&self) -> bool {
+        self.quit
+    }
+}
+
+/// Plays another input source in the terminal, ignoring what is typed
+/// except Ctrl+C.
+pub struct QuitWatcher {
+    inner: Box<dyn InputSource>,
+    terminal: TerminalInput,
+}
+
+impl QuitWatcher {
+    pub fn new(inner: Box<dyn InputSource>) -> Self {
+        Self {
+            inner,
+            terminal: TerminalInput::default(),
+        }
+    }
+}
+
+impl InputSource for QuitWatcher {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) {
+        self.terminal.poll(&mut Vec::new());
+        self.inner.poll(events);
+    }
+
+    fn finished(&self) -> bool {
+        self.terminal.quit || self.inner.finished()
+    }
+
+    fn reset_motion(&mut self) {
+        self.inner.reset_motion();
+    }
+
+    fn clock_readings(&mut self) -> Option<Vec<i64>> {
+        self.inner.clock_readings()
+    }
+}
+
+/// The LWJGL key code for a printable ASCII character, if the key has one.
+fn key_for_char(byte: u8) -> Option<i32> {
+    let key = match byte {
+        b'a'..=b'z' => byte.to_ascii_uppercase(),
+        b'A'..=b'Z'
+        | b'0'..=b'9'
+        | b' '
+        | b'\''
+        | b','
+        | b'-'
+        | b'.'
+        | b'/'
+        | b';'
+        | b'='
+        | b'['
+        | b'\\'
+        | b']'
+        | b'`' => byte,
+        _ => return None,
+    };
+    Some(i32::from(key))
+}
+
+/// Puts the TTY in raw mode on the alternate screen, and restores it when
+/// dropped.
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enable() -> anyhow::Result<Self> {
+        // SAFETY: termios is plain data, and tcgetattr fills it in.
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &raw mut termios) } != 0 {
+            anyhow::bail!("stdin is not a terminal");
+        }
+        let original = termios;
+
+        // SAFETY: termios came from tcgetattr above.
+        unsafe {
+            libc::cfmakeraw(&raw mut termios);
+            // reads return straight away, with or without input
+            termios.c_cc[libc::VMIN] = 0;
+            termios.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw const termios) != 0 {
+                anyhow::bail!("failed to put the terminal in raw mode");
+            }
+        }
+
+        // alternate screen, hide cursor, clear
+        write_stdout(b"\x1b[?1049h\x1b[?25l\x1b[2J");
+        Ok(Self { original })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        write_stdout(b"\x1b[0m\x1b[?25h\x1b[?1049l");
+        // SAFETY: restores the settings read in `enable`.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw const self.original);
+        }
+    }
+}
+
+fn write_stdout(bytes: &[u8]) {
+    let mut stdout = std::io::stdout().lock();
+    if let Err(e) = stdout.write_all(bytes).and_then(|()| stdout.flush()) {
+        log::error!("failed to write to the terminal: {e}");
+    }
+}
+
+/// Everything the TTY has sent since the last call.
+fn read_stdin() -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        // SAFETY: reads at most `buf.len()` bytes into `buf`.
+        let read = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        let Ok(read) = usize::try_from(read) else {
+            break;
+        };
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&buf[..read]);
+    }
+    bytes
+}
+
+/// Columns and rows, falling back to 80x24 if the size is unknown.
+fn terminal_size() -> (usize, usize) {
+    // SAFETY: winsize is plain data, and TIOCGWINSZ fills it in.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &raw mut size) } == 0;
+    if ok && size.ws_col > 0 && size.ws_row > 0 {
+        (usize::from(size.ws_col), usize::from(size.ws_row))
+    } else {
+        (80, 24)
+    }
+}
+
+/// Appends escape codes drawing `pixels` into `columns` x `rows` cells, each
+/// cell showing two pixels with `▀`. The image is scaled to fit and keeps
+/// its aspect ratio.
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::cast_sign_loss)]
+pub fn render_frame(
+    out: &mut String,
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    columns: usize,
+    rows: usize,
+) {
+    let scale = (columns as f32 / width as f32).min((rows * 2) as f32 / height as f32);
+    let cells_w = ((width as f32 * scale) as usize).clamp(1, columns);
+    let pixels_h = ((height as f32 * scale) as usize).clamp(2, rows * 2);
+    let cells_h = pixels_h / 2;
+
+    let sample = |cx: usize, py: usize| -> [u8; 3] {
+        let x = cx * width / cells_w;
+        let y = py * height / pixels_h;
+        let i = (y * width + x) * 4;
+        [pixels[i], pixels[i + 1], pixels[i + 2]]
+    };
+
+    out.clear();
+    let mut last: Option<([u8; 3], [u8; 3])> = None;
+    for row in 0..cells_h {
+        let _ = write!(out, "\x1b[{};1H", row + 1);
+        for column in 0..cells_w {
+            let top = sample(column, row * 2);
+            let bottom = sample(column, row * 2 + 1);
+            if last != Some((top, bottom)) {
+                let _ = write!(
+                    out,
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                    top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+                );
+                last = Some((top, bottom));
+            }
+            out.push('▀');
+        }
+    }
+    out.push_str("\x1b[0m");
+}
+
+/// Draws queued text straight into the frame, since there is no GPU pass to
+/// put it on.
+fn draw_queued_text(pixels: &mut [u8], width: usize, height: usize, queued: &[QueuedText]) {
+    let fonts = get_font_registry().lock();
+    let mut surface = Surface {
+        pixels,
+        width,
+        height,
+    };
+    for item in queued {
+        if let Some(font) = fonts.find_font(item.font) {
+            font.draw(
+                &mut surface,
+                &item.text,
+                item.x,
+                item.y,
+                item.color,
+                item.scale,
+                true,
+            );
+        }
+    }
+    drop(fonts);
+}
+
+/// Runs the cartridge until Ctrl+C, drawing to the terminal instead of a
+/// window.
+pub fn run(wasm: &mut WASMRuntime, filters: &FilterPipeline) -> anyhow::Result<()> {
+    let resolution = wasm.store.get_mut().data().resolution;
+    let width = resolution.width as usize;
+    let height = resolution.height as usize;
+    let mut fb_buf = vec![0u8; resolution.pixels() * 4];
+    let mut frame_buf = Vec::new();
+    let mut filtered = Vec::new();
+    let mut out = String::new();
+
+    let _raw = RawTerminal::enable()?;
+    log::info!("rendering to the terminal, press Ctrl+C to quit");
+
+    for frame in 0u64.. {
+        let start = Instant::now();
+        next_profiler_frame();
+        next_host_call_frame();
+
+        if !wasm.store.get_mut().data_mut().poll_input() {
+            break;
+        }
+
+        begin_profiler("audio update");
+        {
+            get_raw_audio_manager().lock().update();
+        } // release lock
+
+        rebegin_profiler("WASM update");
+        wasm.update()?;
+
+        rebegin_profiler("copy framebuffer");
+        wasm.sync_framebuffer(&mut fb_buf)?;
+        let queued = wasm.take_queued_text();
+
+        if frame % RENDER_EVERY == 0 {
+            rebegin_profiler("draw terminal");
+            frame_buf.clone_from(&fb_buf);
+            draw_queued_text(&mut frame_buf, width, height, &queued);
+            let (columns, rows) = terminal_size();
+            if filters.is_active() {
+                let (out_w, out_h) = filters.filter_frame(&frame_buf, resolution, &mut filtered);
+                render_frame(&mut out, &filtered, out_w, out_h, columns, rows);
+            } else {
+                render_frame(&mut out, &frame_buf, width, height, columns, rows);
+            }
+            write_stdout(out.as_bytes());
+        }
+        end_profiler();
+
+        std::thread::sleep(UPDATE_INTERVAL.saturating_sub(start.elapsed()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feed_maps_keys() {
+        let mut input = TerminalInput::default();
+        input.feed(b"aZ\x1b[A\x1bOD\r\x7f");
+
+        for key in [
+            65,
+            90,
+            KEY_LEFT_SHIFT,
+            KEY_UP,
+            KEY_LEFT,
+            KEY_ENTER,
+            KEY_BACKSPACE,
+        ] {
+            assert!(input.is_key_down(key), "key {key}");
+        }
+        assert!(!input.is_key_down(KEY_ESCAPE));
+        assert_eq!(input.pop_char(), Some('a'));
+        assert_eq!(input.pop_char(), Some('Z'));
+        assert_eq!(input.pop_char(), None);
+        assert!(!input.quit);
+    }
+
+    #[test]
+    fn feed_handles_escape_and_quit() {
+        let mut input = TerminalInput::default();
+        input.feed(b"\x1b");
+        assert!(input.is_key_down(KEY_ESCAPE));
+
+        input.feed(b"\x03");
+        assert!(input.quit);
+    }
+
+    #[test]
+    fn render_keeps_aspect_and_skips_repeated_colors() {
+        // 4x4 image, top half red and bottom half blue
+        let mut pixels = Vec::new();
+        for y in 0..4 {
+            let color = if y < 2 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            };
+            for _ in 0..4 {
+                pixels.extend_from_slice(&color);
+            }
+        }
+
+        let mut out = String::new();
+        render_frame(&mut out, &pixels, 4, 4, 80, 2);
+
+        // 2 rows of cells fit 4 pixel rows, so the image is 4 cells wide
+        assert_eq!(out.matches('▀').count(), 8);
+        assert_eq!(out.matches("38;2;255;0;0m").count(), 1);
+        assert_eq!(out.matches("38;2;0;0;255m").count(), 1);
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
+    path::PathBuf,
     sync::OnceLock,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use macroquad::input::{KeyCode, MouseButton};
@@ -136,12 +137,13 @@ pub const fn map_key(key: i32) -> KeyCode {
     }
 }
 
-pub const fn map_button(button: i32) -> MouseButton {
+// map to LWJGL button, -1 for buttons it has none for
+pub const fn lwjgl_button(button: MouseButton) -> i32 {
     match button {
-        0 => MouseButton::Left,
-        1 => MouseButton::Right,
-        2 => MouseButton::Middle,
-        _ => MouseButton::Unknown,
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Unknown => -1,
     }
 }
 
@@ -153,3 +155,14 @@ pub fn get_time_nanos() -> i64 {
     let elapsed: Duration = start.elapsed();
     elapsed.as_nanos() as i64
 }
+
+/// A fresh file name in the working directory for dumps triggered by a
+/// hotkey or console command, e.g. `trace-1700000000.json`.
+pub fn timestamped_path(prefix: &str, ext: &str) -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    std::env::current_dir()
+        .unwrap()
+        .join(format!("{prefix}-{secs}.{ext}"))
+}
@@ -1,24 +1,261 @@
 #![allow(clippy::missing_panics_doc)]
 #![allow(clippy::missing_errors_doc)]
+use std::path::Path;
+
+use anyhow::Context;
 use fast_cell::FastCell;
 use wasmtime::{
     Cache, CacheConfig, Config, Engine, Instance, InstanceAllocationStrategy, Linker, Memory,
-    Module, PoolingAllocationConfig, Store, Strategy,
+    Module, PoolingAllocationConfig, Store, Strategy, TypedFunc, UpdateDeadline, WasmParams,
+    WasmResults,
 };
 
 use crate::{
-    SCREEN_HEIGHT, SCREEN_WIDTH,
+    cartridge::{Resolution, get_resolution},
+    cartridge_log::CartridgeLog,
+    cli::CliArgs,
+    clock::GuestClock,
+    dirty_region::{DirtyRect, copy_rect, sync_changed_rows},
+    font::QueuedText,
+    gamepad::{Gamepads, KeyboardPad},
+    host_calls::{instrument_host_calls, wants_instrumentation},
+    input_source::{
+        InputEvent, InputSource, InputState, MovieRecorder, NullSource, open_input_source,
+    },
+    keyboard::{KeyEvent, KeyEventKind},
+    keymap::KeyMap,
     modules::{
         audio::link_audio, console::link_console, framebuffer::link_framebuffer, gpu::link_gpu,
-        input::link_input, memory::link_memory, storage::link_storage, system::link_system,
+        input::link_input, memory::link_memory, profiler::link_profiler, storage::link_storage,
+        system::link_system, text::link_text,
     },
-    utils::get_time_nanos,
+    panic_capture::explain_trap,
+    pixel_format::{Palette, PixelFormat, convert_to_rgba, default_palette},
+    present::PresentedFrames,
+    profiler::end_profiler,
+    sampler::{GuestSampler, start_epoch_ticker},
 };
 
 pub type WASMPointer = u32;
 pub type WASMPointerMut = u32;
+#[allow(clippy::struct_excessive_bools)]
 pub struct WASMHostState {
+    pub resolution: Resolution,
+    pub log: CartridgeLog,
+    pub clock: GuestClock,
     pub cursor_grabbed: bool,
+    /// Set through `input::set_cursor_visible`. `grab_mouse` hides the
+    /// cursor either way; this is what `release_mouse` goes back to.
+    pub cursor_hidden: bool,
+    pub input: InputState,
+    pub input_source: Box<dyn InputSource>,
+    /// Set when input comes from a movie or script, which already hold what
+    /// the cartridge should get: neither host screens nor key bindings
+    /// change it.
+    pub input_replayed: bool,
+    /// Records what the cartridge gets with `--record-input`.
+    pub recorder: Option<MovieRecorder>,
+    pub keymap: KeyMap,
+    /// Set while a host screen, like the key binding screen or the dev
+    /// console, takes the keyboard. Key events and text from the window or
+    /// terminal are dropped instead of reaching the cartridge, and only one
+    /// screen takes it at a time.
+    pub keyboard_captured: bool,
+    pub gamepads: Gamepads,
+    /// Scopes the cartridge opened through `profiler::begin_scope` and has
+    /// not closed yet.
+    pub guest_scope_depth: usize,
+    pub sampler: Option<GuestSampler>,
+    /// Union of the rects reported through `framebuffer::mark_dirty_rect`
+    /// since the last sync.
+    pub dirty_rect: Option<DirtyRect>,
+    /// Set once the cartridge reports dirty rects itself, after which the
+    /// host stops diffing the framebuffer.
+    pub reports_dirty_rects: bool,
+    /// Text to draw over the next frame, from `text::queue_text`.
+    pub queued_text: Vec<QueuedText>,
+    pub pixel_format: PixelFormat,
+    pub palette: Box<Palette>,
+    /// Set once the cartridge calls `framebuffer::present`, after which the
+    /// host stops reading the framebuffer from `get_framebuffer_ptr`.
+    pub presented: Option<PresentedFrames>,
+    /// Set when running with `--terminal`. There is no window then, so
+    /// nothing may touch macroquad's input or GL state.
+    pub headless: bool,
+}
+
+impl WASMHostState {
+    fn new(args: &CliArgs) -> anyhow::Result<Self> {
+        let log = CartridgeLog::new(&args.cartridge, args.log_level, args.log_file.as_deref())
+            .context("failed to open the cartridge log file")?;
+
+        let resolution = get_resolution();
+        let input_source = open_input_source(args, resolution)?;
+        let recorder = args
+            .record_input
+            .as_deref()
+            .map(MovieRecorder::create)
+            .transpose()?;
+        let keymap = KeyMap::load(&args.cartridge)?;
+        let mut gamepads = Gamepads::default();
+        if args.keyboard_gamepad {
+            gamepads.add_source(Box::new(KeyboardPad::new(0)));
+        }
+
+        let mut input = InputState::default();
+        input.mouse.settings = args.mouse;
+        let mut clock = GuestClock::new();
+        if recorder.is_some() {
+            clock.record();
+        }
+
+        Ok(Self {
+            resolution,
+            log,
+            clock,
+            cursor_grabbed: false,
+            cursor_hidden: false,
+            input,
+            input_source,
+            input_replayed: args.play_input.is_some() || args.input_script.is_some(),
+            recorder,
+            keymap,
+            keyboard_captured: false,
+            gamepads,
+            guest_scope_depth: 0,
+            sampler: None,
+            dirty_rect: None,
+            reports_dirty_rects: false,
+            queued_text: Vec::new(),
+            pixel_format: PixelFormat::default(),
+            palette: default_palette(),
+            presented: None,
+            headless: args.terminal,
+        })
+    }
+
+    #[must_use]
+    pub const fn is_headless(&self) -> bool {
+        self.headless
+    }
+
+    /// Reads the next frame's input from the input source. Returns false
+    /// once the source is finished and the session should end.
+    pub fn poll_input(&mut self) -> bool {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_clock(&self.clock.take_recorded());
+        }
+        self.input.begin_frame();
+        let mut events = Vec::new();
+        self.input_source.poll(&mut events);
+        if let Some(readings) = self.input_source.clock_readings() {
+            self.clock.replay(readings);
+        }
+        for event in events {
+            let event = match event {
+                // relative motion only counts while the cursor is grabbed,
+                // and a grabbed cursor stays where it was
+                InputEvent::MouseMove { .. } if self.cursor_grabbed => continue,
+                InputEvent::MouseDelta { .. } if !self.cursor_grabbed => continue,
+                event if self.input_replayed => event,
+                InputEvent::Key(_) | InputEvent::Char(_) if self.keyboard_captured => continue,
+                // the scancode still names the key that was pressed
+                InputEvent::Key(mut key) => {
+                    key.key = self.keymap.map(key.key);
+                    InputEvent::Key(key)
+                }
+                other => other,
+            };
+            self.apply_input(event);
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.next_frame();
+        }
+
+        let keyboard = &self.input.keyboard;
+        self.gamepads.poll(&|key| keyboard.is_key_down(key));
+        !self.input_source.finished()
+    }
+
+    /// Applies an event the cartridge should get, recording it if asked.
+    fn apply_input(&mut self, event: InputEvent) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(event);
+        }
+        self.input.apply(event);
+    }
+
+    /// Gives the keyboard to a host screen, or back to the cartridge. Keys
+    /// held when a screen takes it are released, as the cartridge won't see
+    /// them come up.
+    pub fn set_keyboard_captured(&mut self, captured: bool) {
+        if captured && !self.keyboard_captured && !self.input_replayed {
+            for key in self.input.keyboard.held_keys() {
+                let release = KeyEvent::from_lwjgl(KeyEventKind::Release, key, 0);
+                self.apply_input(InputEvent::Key(release));
+            }
+        }
+        self.keyboard_captured = captured;
+    }
+
+    /// Drops the input source and finishes a movie being recorded.
+    pub fn close_input(&mut self) {
+        self.input_source = Box::new(NullSource);
+        if let Some(mut recorder) = self.recorder.take() {
+            recorder.record_clock(&self.clock.take_recorded());
+        }
+    }
+}
+
+/// Cartridge exports, resolved and type-checked once at instantiation.
+pub struct WASMExports {
+    pub update: TypedFunc<i64, ()>,
+    pub get_framebuffer_ptr: TypedFunc<(), i32>,
+    pub main: Option<TypedFunc<(), ()>>,
+    pub gpu_main: Option<TypedFunc<(), ()>>,
+}
+
+impl WASMExports {
+    pub fn resolve(store: &mut Store<WASMHostState>, instance: &Instance) -> anyhow::Result<Self> {
+        Ok(Self {
+            update: required_export(store, instance, "update", "fn update(nanos: i64)")?,
+            get_framebuffer_ptr: required_export(
+                store,
+                instance,
+                "get_framebuffer_ptr",
+                "fn get_framebuffer_ptr() -> i32",
+            )?,
+            main: optional_export(store, instance, "main", "fn main()")?,
+            gpu_main: optional_export(store, instance, "gpu_main", "fn gpu_main()")?,
+        })
+    }
+}
+
+fn required_export<Params: WasmParams, Results: WasmResults>(
+    store: &mut Store<WASMHostState>,
+    instance: &Instance,
+    name: &str,
+    signature: &str,
+) -> anyhow::Result<TypedFunc<Params, Results>> {
+    optional_export(store, instance, name, signature)?
+        .ok_or_else(|| anyhow::anyhow!("cartridge does not export `{signature}`"))
+}
+
+/// Returns `None` if the export is missing, and an error if it exists but
+/// does not have the expected signature.
+fn optional_export<Params: WasmParams, Results: WasmResults>(
+    store: &mut Store<WASMHostState>,
+    instance: &Instance,
+    name: &str,
+    signature: &str,
+) -> anyhow::Result<Option<TypedFunc<Params, Results>>> {
+    instance
+        .get_func(&mut *store, name)
+        .map(|func| {
+            func.typed(&*store)
+                .with_context(|| format!("cartridge export `{name}` must be `{signature}`"))
+        })
+        .transpose()
 }
 
 pub struct WASMRuntime {
@@ -27,72 +264,162 @@ pub struct WASMRuntime {
     pub memory: FastCell<Option<Memory>>,
     pub linker: FastCell<Linker<WASMHostState>>,
     pub instance: FastCell<Option<Instance>>,
+    pub exports: FastCell<Option<WASMExports>>,
+    /// Scratch space for framebuffers that are not already RGBA.
+    pub converted: FastCell<Vec<u8>>,
 }
 
 impl WASMRuntime {
-    pub fn main(&mut self) -> anyhow::Result<()> {
+    /// Calls the cartridge's `main` export, if it has one.
+    pub fn main(&mut self) -> anyhow::Result<bool> {
         let store = self.store.get_mut();
-        let instance = self.instance.get_mut();
-        instance
-            .unwrap()
-            .get_typed_func::<(), ()>(&mut *store, "main")?
-            .call(store, ())
+        let exports = self.exports.get_mut().as_ref().unwrap();
+        exports.main.as_ref().map_or(Ok(false), |main| {
+            guest_call(store, |store| main.call(store, ())).map(|()| true)
+        })
     }
 
-    pub fn gpu_main(&mut self) -> anyhow::Result<()> {
+    /// Calls the cartridge's `gpu_main` export, if it has one.
+    pub fn gpu_main(&mut self) -> anyhow::Result<bool> {
         let store = self.store.get_mut();
-        let instance = self.instance.get_mut();
-        instance
-            .unwrap()
-            .get_typed_func::<(), ()>(&mut *store, "gpu_main")?
-            .call(store, ())
+        let exports = self.exports.get_mut().as_ref().unwrap();
+        exports.gpu_main.as_ref().map_or(Ok(false), |gpu_main| {
+            guest_call(store, |store| gpu_main.call(store, ())).map(|()| true)
+        })
     }
 
     #[allow(clippy::cast_possible_truncation)]
     pub fn update(&mut self) -> anyhow::Result<()> {
         let store = self.store.get_mut();
-        let instance = self.instance.get_mut();
-        instance
-            .unwrap()
-            .get_typed_func::<i64, ()>(&mut *store, "update")?
-            .call(store, get_time_nanos())?;
+        let update = &self.exports.get_mut().as_ref().unwrap().update;
+
+        set_sampling(store, true);
+        let now = store.data_mut().clock.now();
+        let result = guest_call(store, |store| update.call(store, now));
+        set_sampling(store, false);
+        result?;
+
+        // close scopes the cartridge left open so they stay nested under
+        // the emulator's "WASM update" scope
+        let state = store.data_mut();
+        for _ in 0..state.guest_scope_depth {
+            end_profiler();
+        }
+        state.guest_scope_depth = 0;
+
         Ok(())
     }
 
+    pub fn save_guest_profile(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.store
+            .get_mut()
+            .data_mut()
+            .sampler
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("guest sampling is not enabled (see --guest-profile)"))?
+            .save(path)
+    }
+
+    /// Text queued by the cartridge since the last call.
+    pub fn take_queued_text(&mut self) -> Vec<QueuedText> {
+        std::mem::take(&mut self.store.get_mut().data_mut().queued_text)
+    }
+
     #[allow(clippy::cast_sign_loss)]
     fn get_framebuffer_ptr(&mut self) -> anyhow::Result<usize> {
         let store = self.store.get_mut();
-        let instance = self.instance.get_mut();
+        let exports = self.exports.get_mut().as_ref().unwrap();
 
-        Ok(instance
-            .unwrap()
-            .get_typed_func::<(), i32>(&mut *store, "get_framebuffer_ptr")?
-            .call(&mut *store, ())? as usize)
+        Ok(exports.get_framebuffer_ptr.call(store, ())? as usize)
     }
 
     pub fn get_framebuffer(&mut self) -> anyhow::Result<Vec<u8>> {
-        let buffer_ptr = self.get_framebuffer_ptr()?;
-        let store = self.store.get_mut();
-        let memory = self.memory.get_mut().unwrap();
-
-        let mut pixels = vec![0u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize];
-        memory.read(&mut *store, buffer_ptr, &mut pixels)?;
-
+        let mut pixels = vec![0u8; self.store.get_mut().data().resolution.pixels() * 4];
+        self.get_framebuffer_into(&mut pixels)?;
         Ok(pixels)
     }
 
+    /// Reads the whole guest framebuffer into `pixels` as RGBA.
     pub fn get_framebuffer_into(&mut self, pixels: &mut [u8]) -> anyhow::Result<()> {
-        let buffer_ptr = self.get_framebuffer_ptr()?;
+        pixels.copy_from_slice(self.read_framebuffer()?);
+        Ok(())
+    }
+
+    /// The guest framebuffer as RGBA, converted if the cartridge uses another
+    /// pixel format. This is the last presented frame for cartridges that
+    /// call `framebuffer::present`.
+    fn read_framebuffer(&mut self) -> anyhow::Result<&[u8]> {
+        let buffer_ptr = if self.store.get_mut().data().presented.is_some() {
+            None
+        } else {
+            Some(self.get_framebuffer_ptr()?)
+        };
         let store = self.store.get_mut();
         let memory = self.memory.get_mut().unwrap();
+        let (mem, state) = memory.data_and_store_mut(store);
 
-        memory.read(&mut *store, buffer_ptr, pixels)?;
+        let format = state.pixel_format;
+        let len = state.resolution.pixels() * format.bytes_per_pixel();
+        let src = match (buffer_ptr, &state.presented) {
+            (Some(ptr), _) => mem.get(ptr..ptr + len),
+            (None, Some(frames)) => frames.front().get(..len),
+            (None, None) => unreachable!(),
+        }
+        .ok_or_else(|| anyhow::anyhow!("framebuffer is out of bounds"))?;
 
-        Ok(())
+        if format == PixelFormat::Rgba8888 {
+            return Ok(src);
+        }
+        let converted = self.converted.get_mut();
+        convert_to_rgba(format, src, &state.palette, converted);
+        Ok(converted)
     }
+
+    /// Brings `pixels` up to date with the guest framebuffer, copying only
+    /// what changed, and returns the region that needs to be uploaded.
+    pub fn sync_framebuffer(&mut self, pixels: &mut [u8]) -> anyhow::Result<Option<DirtyRect>> {
+        // keep showing the last complete frame until the next present
+        if let Some(frames) = &mut self.store.get_mut().data_mut().presented
+            && !frames.take_fresh()
+        {
+            return Ok(None);
+        }
+
+        let state = self.store.get_mut().data_mut();
+        let width = state.resolution.width as usize;
+        let height = state.resolution.height as usize;
+        let reported = state.dirty_rect.take();
+        let reports_dirty_rects = state.reports_dirty_rects;
+
+        let src = self.read_framebuffer()?;
+        if reports_dirty_rects {
+            if let Some(rect) = reported {
+                copy_rect(src, pixels, width, rect);
+            }
+            return Ok(reported);
+        }
+
+        Ok(sync_changed_rows(src, pixels, width, height))
+    }
+}
+
+/// Runs a call into the cartridge, adding its panic message to the error if
+/// it panicked.
+pub fn guest_call<R>(
+    store: &mut Store<WASMHostState>,
+    call: impl FnOnce(&mut Store<WASMHostState>) -> anyhow::Result<R>,
+) -> anyhow::Result<R> {
+    store.data_mut().log.begin_call();
+    call(store).map_err(|e| explain_trap(e, store.data().log.call_messages()))
 }
 
-pub fn init_wasm(wasm: Vec<u8>) -> anyhow::Result<WASMRuntime> {
+fn set_sampling(store: &mut Store<WASMHostState>, active: bool) {
+    if let Some(sampler) = store.data_mut().sampler.as_mut() {
+        sampler.set_active(active);
+    }
+}
+
+pub fn init_wasm(wasm: Vec<u8>, args: &CliArgs) -> anyhow::Result<WASMRuntime> {
     let mut config = Config::new();
     config.strategy(Strategy::Cranelift);
     config.signals_based_traps(true);
@@ -100,6 +427,7 @@ pub fn init_wasm(wasm: Vec<u8>) -> anyhow::Result<WASMRuntime> {
     config.memory_guard_size(1 << 31);
     config.memory_init_cow(true);
     config.parallel_compilation(true);
+    config.epoch_interruption(args.guest_profile.is_some());
 
     let mut cache_config = CacheConfig::new();
     cache_config.with_directory(std::env::current_dir()?.join("cache"));
@@ -117,12 +445,21 @@ pub fn init_wasm(wasm: Vec<u8>) -> anyhow::Result<WASMRuntime> {
     log::info!("engine OK");
     let module = Module::new(&engine, wasm)?;
     log::info!("module OK");
-    let store = Store::new(
-        &engine,
-        WASMHostState {
-            cursor_grabbed: false,
-        },
-    );
+    let mut store = Store::new(&engine, WASMHostState::new(args)?);
+    if args.guest_profile.is_some() {
+        let module_name = args
+            .cartridge
+            .file_stem()
+            .map_or_else(|| "cartridge".into(), |stem| stem.to_string_lossy());
+        store.data_mut().sampler = Some(GuestSampler::new(&engine, &module, &module_name)?);
+        store.epoch_deadline_callback(|store| {
+            GuestSampler::sample(store);
+            Ok(UpdateDeadline::Continue(1))
+        });
+        store.set_epoch_deadline(1);
+        start_epoch_ticker(engine.clone());
+        log::info!("guest sampling enabled");
+    }
     log::info!("store OK");
     let linker = <Linker<WASMHostState>>::new(&engine);
     log::info!("linker OK");
@@ -132,6 +469,8 @@ pub fn init_wasm(wasm: Vec<u8>) -> anyhow::Result<WASMRuntime> {
         linker: FastCell::new(linker),
         memory: FastCell::new(None),
         instance: FastCell::new(None),
+        exports: FastCell::new(None),
+        converted: FastCell::new(Vec::new()),
     };
     log::info!("runtime OK");
 
@@ -151,6 +490,14 @@ pub fn init_wasm(wasm: Vec<u8>) -> anyhow::Result<WASMRuntime> {
     log::info!("audio linked");
     link_gpu(&runtime)?;
     log::info!("gpu linked");
+    link_profiler(&runtime)?;
+    log::info!("profiler linked");
+    link_text(&runtime)?;
+    log::info!("text linked");
+
+    if wants_instrumentation(args) {
+        instrument_host_calls(&mut runtime, args)?;
+    }
 
     let linker = runtime.linker.get_mut();
     let store = runtime.store.get_mut();
@@ -158,9 +505,11 @@ pub fn init_wasm(wasm: Vec<u8>) -> anyhow::Result<WASMRuntime> {
     let memory = instance
         .get_export(&mut *store, "memory")
         .and_then(wasmtime::Extern::into_memory)
-        .ok_or_else(|| anyhow::anyhow!("module did not export memory"))?;
+        .ok_or_else(|| anyhow::anyhow!("cartridge does not export `memory`"))?;
+    let exports = WASMExports::resolve(store, &instance)?;
     *runtime.memory.get_mut() = Some(memory);
     *runtime.instance.get_mut() = Some(instance);
+    *runtime.exports.get_mut() = Some(exports);
     log::info!("linked module!");
 
     Ok(runtime)
@@ -0,0 +1,147 @@
+use macroquad::{
+    input::{
+        KeyCode, MouseButton, get_char_pressed,
+        utils::{register_input_subscriber, repeat_all_miniquad_input},
+    },
+    miniquad::{
+        EventHandler, KeyMods,
+        window::{dpi_scale, screen_size},
+    },
+};
+
+use crate::{
+    cartridge::Resolution,
+    input_source::{InputEvent, InputSource},
+    keyboard::{KeyEvent, KeyEventKind, modifier_bits},
+    utils::lwjgl_button,
+};
+
+/// Windows reports the wheel in 1/120ths of a notch, the other backends in
+/// notches.
+#[cfg(target_os = "windows")]
+const WHEEL_UNITS_PER_NOTCH: f64 = 120.0;
+#[cfg(not(target_os = "windows"))]
+const WHEEL_UNITS_PER_NOTCH: f64 = 1.0;
+
+/// Reads keyboard and mouse events from the window through a macroquad
+/// input subscriber. Unlike `is_key_pressed` and `mouse_wheel` this keeps
+/// repeats, modifiers and every wheel event of a frame.
+///
+/// miniquad only reports the left, right and middle buttons and has no
+/// enter/leave events, so extra buttons never go down and the cursor
+/// counts as inside the window while its last reported position is.
+///
+/// Relative motion is the difference between motion events. While the
+/// cursor is grabbed macroquad reports raw device motion added onto the
+/// last position, so this is the raw motion then.
+pub struct WindowSource {
+    subscriber: usize,
+    resolution: Resolution,
+    /// Last motion event position, in physical window pixels.
+    last_motion: Option<(f32, f32)>,
+}
+
+impl WindowSource {
+    pub fn new(resolution: Resolution) -> Self {
+        Self {
+            subscriber: register_input_subscriber(),
+            resolution,
+            last_motion: None,
+        }
+    }
+}
+
+impl InputSource for WindowSource {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) {
+        #[allow(clippy::cast_precision_loss)]
+        let scale = self.resolution.scale() as f32 * dpi_scale();
+        let mut collector = Collector {
+            events,
+            scale,
+            last_motion: &mut self.last_motion,
+        };
+        repeat_all_miniquad_input(&mut collector, self.subscriber);
+
+        // read from macroquad's queue rather than the subscriber, so text
+        // typed into the dev console doesn't reach the cartridge
+        while let Some(ch) = get_char_pressed() {
+            events.push(InputEvent::Char(ch));
+        }
+    }
+
+    fn reset_motion(&mut self) {
+        // macroquad moves the cursor between its real and grabbed positions
+        self.last_motion = None;
+    }
+}
+
+struct Collector<'a> {
+    events: &'a mut Vec<InputEvent>,
+    /// Physical window pixels per framebuffer pixel.
+    scale: f32,
+    last_motion: &'a mut Option<(f32, f32)>,
+}
+
+impl EventHandler for Collector<'_> {
+    fn update(&mut self) {}
+
+    fn draw(&mut self) {}
+
+    fn key_down_event(&mut self, keycode: KeyCode, keymods: KeyMods, repeat: bool) {
+        let kind = if repeat {
+            KeyEventKind::Repeat
+        } else {
+            KeyEventKind::Press
+        };
+        self.events.push(InputEvent::Key(KeyEvent::new(
+            kind,
+            keycode,
+            modifier_bits(keymods),
+        )));
+    }
+
+    fn key_up_event(&mut self, keycode: KeyCode, keymods: KeyMods) {
+        self.events.push(InputEvent::Key(KeyEvent::new(
+            KeyEventKind::Release,
+            keycode,
+            modifier_bits(keymods),
+        )));
+    }
+
+    fn mouse_wheel_event(&mut self, x: f32, y: f32) {
+        self.events.push(InputEvent::Wheel {
+            x: f64::from(x) / WHEEL_UNITS_PER_NOTCH,
+            y: f64::from(y) / WHEEL_UNITS_PER_NOTCH,
+        });
+    }
+
+    fn mouse_button_down_event(&mut self, button: MouseButton, _x: f32, _y: f32) {
+        self.events.push(InputEvent::MouseButton {
+            button: lwjgl_button(button),
+            down: true,
+        });
+    }
+
+    fn mouse_button_up_event(&mut self, button: MouseButton, _x: f32, _y: f32) {
+        self.events.push(InputEvent::MouseButton {
+            button: lwjgl_button(button),
+            down: false,
+        });
+    }
+
+    fn mouse_motion_event(&mut self, x: f32, y: f32) {
+        if let Some((last_x, last_y)) = self.last_motion.replace((x, y)) {
+            self.events.push(InputEvent::MouseDelta {
+                dx: f64::from(x - last_x),
+                dy: f64::from(y - last_y),
+            });
+        }
+
+        let (width, height) = screen_size();
+        self.events.push(InputEvent::MouseMove {
+            x: x / self.scale,
+            y: y / self.scale,
+            inside: (0.0..width).contains(&x) && (0.0..height).contains(&y),
+        });
+    }
+}